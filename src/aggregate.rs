@@ -0,0 +1,388 @@
+use core::cmp::Ordering;
+use core::ops::{Add, Bound, RangeBounds};
+use core::ptr::null_mut;
+
+use crate::{Node, NodeAlloc, NodePtr, PtrExt, RawNodePtr, RBTree, SetKey};
+use crate::kv::{Color, Key};
+
+/// An associative operation with an identity, whose result an
+/// `AggregateNode` caches for its subtree. The summary of a single value is
+/// made with `From<&V>`.
+///
+/// `combine` must not panic, as it runs while the tree is being rebalanced:
+/// a panic there aborts the process. This includes arithmetic overflow in a
+/// `Sum` built with overflow checks.
+pub trait Monoid: Clone {
+    fn identity() -> Self;
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// The sum of the values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sum<T>(pub T);
+
+impl<T: Clone + Default + Add<Output = T>> Monoid for Sum<T> {
+    fn identity() -> Self {
+        Sum(T::default())
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Sum(self.0.clone() + other.0.clone())
+    }
+}
+
+impl<'a, T: Clone> From<&'a T> for Sum<T> {
+    fn from(value: &'a T) -> Self {
+        Sum(value.clone())
+    }
+}
+
+/// The least value, if any.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Min<T>(pub Option<T>);
+
+impl<T: Clone + Ord> Monoid for Min<T> {
+    fn identity() -> Self {
+        Min(None)
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Min(Some(a.min(b).clone())),
+            (Some(_), None) => self.clone(),
+            _ => other.clone()
+        }
+    }
+}
+
+impl<'a, T: Clone> From<&'a T> for Min<T> {
+    fn from(value: &'a T) -> Self {
+        Min(Some(value.clone()))
+    }
+}
+
+/// The greatest value, if any.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Max<T>(pub Option<T>);
+
+impl<T: Clone + Ord> Monoid for Max<T> {
+    fn identity() -> Self {
+        Max(None)
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Max(Some(a.max(b).clone())),
+            (Some(_), None) => self.clone(),
+            _ => other.clone()
+        }
+    }
+}
+
+impl<'a, T: Clone> From<&'a T> for Max<T> {
+    fn from(value: &'a T) -> Self {
+        Max(Some(value.clone()))
+    }
+}
+
+/// The number of values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Count(pub usize);
+
+impl Monoid for Count {
+    fn identity() -> Self {
+        Count(0)
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Count(self.0 + other.0)
+    }
+}
+
+/// A summary which includes the number of values, so keys can be counted
+/// and ranked in logarithmic time.
+pub trait Counted: Monoid {
+    fn count(&self) -> usize;
+}
+
+impl Counted for Count {
+    fn count(&self) -> usize {
+        self.0
+    }
+}
+
+/// Values which are themselves counts, so that every key is counted as many
+/// times as its value, as in a multiset.
+impl Counted for Sum<usize> {
+    fn count(&self) -> usize {
+        self.0
+    }
+}
+
+impl<'a, V> From<&'a V> for Count {
+    fn from(_: &'a V) -> Self {
+        Count(1)
+    }
+}
+
+/// A key value node which caches the summary `M` of the values in its
+/// subtree, in key order, for `RBTree::aggregate`.
+pub struct AggregateNode<K: Key, V, M> {
+    left: AggregateNodePtr<K, V, M>,
+    right: AggregateNodePtr<K, V, M>,
+    color: Color,
+    key: K,
+    value: V,
+    summary: M
+}
+
+impl<K: Key, V, M: Monoid + for<'a> From<&'a V>> AggregateNode<K, V, M> {
+    pub fn new(key: K, value: V) -> Self {
+        let summary = M::from(&value);
+        AggregateNode {
+            left: AggregateNodePtr::NIL,
+            right: AggregateNodePtr::NIL,
+            color: Color::RED,
+            key,
+            value,
+            summary
+        }
+    }
+}
+
+impl<K: Key, V, M> AggregateNode<K, V, M> {
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    /// The summary of the values in the subtree of the node.
+    pub fn summary(&self) -> &M {
+        &self.summary
+    }
+}
+
+impl<K: Key, V: Clone, M: Monoid + for<'a> From<&'a V>> Clone for AggregateNode<K, V, M> {
+    fn clone(&self) -> Self {
+        AggregateNode::new(self.key.clone(), self.value.clone())
+    }
+}
+
+unsafe impl<K: Key, V, M: Monoid + for<'a> From<&'a V>> Node for AggregateNode<K, V, M> {
+    type Key = K;
+    type Ptr = AggregateNodePtr<K, V, M>;
+
+    fn left(&self) -> Self::Ptr {
+        self.left
+    }
+
+    fn set_left(&mut self, ptr: Self::Ptr) {
+        self.left = ptr
+    }
+
+    fn right(&self) -> Self::Ptr {
+        self.right
+    }
+
+    fn set_right(&mut self, ptr: Self::Ptr) {
+        self.right = ptr
+    }
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn is_black(&self) -> bool {
+        self.color == Color::BLACK
+    }
+
+    fn set_black(&mut self) {
+        self.color = Color::BLACK
+    }
+
+    fn set_red(&mut self) {
+        self.color = Color::RED
+    }
+
+    const AUGMENTED: bool = true;
+
+    fn refresh(&mut self, left: Option<&Self>, right: Option<&Self>) {
+        let mut summary = M::from(&self.value);
+        if let Some(left) = left {
+            summary = left.summary.combine(&summary);
+        }
+        if let Some(right) = right {
+            summary = summary.combine(&right.summary);
+        }
+        self.summary = summary;
+    }
+}
+
+impl<K: Key, V, M: Monoid + for<'a> From<&'a V>> SetKey for AggregateNode<K, V, M> {
+    fn set_key(&mut self, key: Self::Key) -> Self::Key {
+        core::mem::replace(&mut self.key, key)
+    }
+}
+
+// The child links of an aggregate node are only dereferenced by the tree
+// owning it, through its allocator, when summaries are refreshed or ranges
+// folded, so only the key, value and summary decide whether it can cross
+// threads.
+unsafe impl<K: Key + Send, V: Send, M: Send> Send for AggregateNode<K, V, M> {}
+unsafe impl<K: Key + Sync, V: Sync, M: Sync> Sync for AggregateNode<K, V, M> {}
+
+pub struct AggregateNodePtr<K: Key, V, M>(*mut AggregateNode<K, V, M>);
+
+impl<K: Key, V, M> Clone for AggregateNodePtr<K, V, M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K: Key, V, M> Copy for AggregateNodePtr<K, V, M> {}
+
+impl<K: Key, V, M: Monoid + for<'a> From<&'a V>> NodePtr<AggregateNode<K, V, M>> for AggregateNodePtr<K, V, M> {
+    const NIL: Self = AggregateNodePtr(null_mut());
+
+    fn is_nil(&self) -> bool {
+        self.0.is_null()
+    }
+}
+
+unsafe impl<K: Key, V, M: Monoid + for<'a> From<&'a V>> RawNodePtr<AggregateNode<K, V, M>> for AggregateNodePtr<K, V, M> {
+    fn from_raw(ptr: *mut AggregateNode<K, V, M>) -> Self {
+        AggregateNodePtr(ptr)
+    }
+
+    fn into_raw(self) -> *mut AggregateNode<K, V, M> {
+        self.0
+    }
+}
+
+impl<K: Key, V, M: Monoid + for<'a> From<&'a V>, A: NodeAlloc<AggregateNode<K, V, M>>> RBTree<AggregateNode<K, V, M>, A> {
+    /// Combines the values with keys in `range`, in key order, visiting a
+    /// single path for each bound.
+    pub fn aggregate<R: RangeBounds<K>>(&self, range: R) -> M {
+        self.fold(self.root, range.start_bound(), range.end_bound())
+    }
+
+    fn fold(&self, ptr: AggregateNodePtr<K, V, M>, start: Bound<&K>, end: Bound<&K>) -> M {
+        if ptr.is_nil() {
+            return M::identity();
+        }
+        let node = ptr.node(&self.alloc);
+        if let (Bound::Unbounded, Bound::Unbounded) = (start, end) {
+            return node.summary.clone();
+        }
+        let after_start = match start {
+            Bound::Included(start) => node.key >= *start,
+            Bound::Excluded(start) => node.key > *start,
+            Bound::Unbounded => true
+        };
+        if !after_start {
+            return self.fold(node.right, start, end);
+        }
+        let before_end = match end {
+            Bound::Included(end) => node.key <= *end,
+            Bound::Excluded(end) => node.key < *end,
+            Bound::Unbounded => true
+        };
+        if !before_end {
+            return self.fold(node.left, start, end);
+        }
+        self.fold(node.left, start, Bound::Unbounded)
+            .combine(&M::from(&node.value))
+            .combine(&self.fold(node.right, Bound::Unbounded, end))
+    }
+}
+
+impl<K: Key, V, M: Counted + for<'a> From<&'a V>, A: NodeAlloc<AggregateNode<K, V, M>>> RBTree<AggregateNode<K, V, M>, A> {
+    /// Counts the keys in `range`, visiting a single path for each bound.
+    pub fn range_count<R: RangeBounds<K>>(&self, range: R) -> usize {
+        let before_start = match range.start_bound() {
+            Bound::Included(start) => self.count_before(start, false),
+            Bound::Excluded(start) => self.count_before(start, true),
+            Bound::Unbounded => 0
+        };
+        let before_end = match range.end_bound() {
+            Bound::Included(end) => self.count_before(end, true),
+            Bound::Excluded(end) => self.count_before(end, false),
+            Bound::Unbounded => Self::summary_count(&self.alloc, self.root)
+        };
+        before_end.saturating_sub(before_start)
+    }
+
+    /// Returns the node holding the value of the given rank, counting from 0
+    /// in key order.
+    pub fn select(&self, rank: usize) -> Option<&AggregateNode<K, V, M>> {
+        let alloc = &self.alloc;
+        let mut rank = rank;
+        let mut ptr = self.root;
+        while !ptr.is_nil() {
+            let node = ptr.node(alloc);
+            let left = Self::summary_count(alloc, node.left);
+            if rank < left {
+                ptr = node.left;
+                continue;
+            }
+            let through = node.summary.count() - Self::summary_count(alloc, node.right);
+            if rank < through {
+                return Some(node);
+            }
+            rank -= through;
+            ptr = node.right;
+        }
+        None
+    }
+
+    /// Returns the node holding the `q` quantile of the values: the first
+    /// one in key order which at least `q` of all the values don't come
+    /// after.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `q` is not within `0.0..=1.0`.
+    pub fn quantile(&self, q: f64) -> Option<&AggregateNode<K, V, M>> {
+        self.select(nearest_rank(q, Self::summary_count(&self.alloc, self.root)))
+    }
+
+    /// Returns the node holding the median value, the lower one of the two
+    /// for an even count.
+    pub fn median(&self) -> Option<&AggregateNode<K, V, M>> {
+        self.quantile(0.5)
+    }
+
+    // The number of keys less than `key`, or with `inclusive`, less than or
+    // equal to it.
+    fn count_before(&self, key: &K, inclusive: bool) -> usize {
+        let alloc = &self.alloc;
+        let mut count = 0;
+        let mut ptr = self.root;
+        while !ptr.is_nil() {
+            let node = ptr.node(alloc);
+            let before = match node.key.cmp(key) {
+                Ordering::Less => true,
+                Ordering::Equal => inclusive,
+                Ordering::Greater => false
+            };
+            if before {
+                count += node.summary.count() - Self::summary_count(alloc, node.right);
+                ptr = node.right;
+            } else {
+                ptr = node.left;
+            }
+        }
+        count
+    }
+
+    fn summary_count(alloc: &A, ptr: AggregateNodePtr<K, V, M>) -> usize {
+        if ptr.is_nil() { 0 } else { ptr.node(alloc).summary.count() }
+    }
+}
+
+// The rank of the `q` quantile of `count` values by the nearest-rank method.
+fn nearest_rank(q: f64, count: usize) -> usize {
+    assert!((0.0..=1.0).contains(&q), "quantile (is {}) should be within 0..=1", q);
+    let position = q * count as f64;
+    let rank = position as usize;
+    if (rank as f64) < position { rank } else { rank.saturating_sub(1) }
+}
@@ -0,0 +1,332 @@
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, collections::TryReserveError, vec::Vec};
+#[cfg(feature = "alloc")]
+use core::alloc::Layout;
+use core::error::Error;
+use core::fmt::{self, Debug, Display, Formatter};
+use core::mem::size_of;
+#[cfg(feature = "alloc")]
+use core::mem::{ManuallyDrop, MaybeUninit};
+
+use crate::{Node, NodePtr};
+
+/// Allocates, releases and dereferences the nodes of a `RBTree`.
+///
+/// Every tree owns an allocator instance which creates and releases all of
+/// its nodes, so nodes may come from a per-tree pool or arena rather than the
+/// global heap. References to nodes are only handed out through a borrow of
+/// the allocator, so they can't outlive the tree owning them.
+///
+/// # Safety
+///
+/// `alloc` must return a non-NIL pointer, which `node` and `node_mut` resolve
+/// to the node moved in, until it is passed to `free`. Pointers to distinct
+/// nodes must resolve to references which don't overlap.
+///
+/// Nodes must only be reachable through the allocator which allocated them,
+/// resolving pointers with no state other than the allocator itself and the
+/// memory it owns. `RBTree<N, A>` is `Send` and `Sync` whenever `N` and `A`
+/// are, regardless of `N::Ptr`, so an allocator sharing its nodes with other
+/// values, e.g. through a thread local pool, must not be `Send` or `Sync`.
+pub unsafe trait NodeAlloc<N: Node> {
+    /// Moves a node into memory managed by the allocator.
+    fn alloc(&mut self, node: N) -> N::Ptr;
+
+    /// Like `alloc`, but hands the node back instead of panicking or aborting
+    /// when there is no memory for it.
+    fn try_alloc(&mut self, node: N) -> Result<N::Ptr, AllocError<N>> {
+        Ok(self.alloc(node))
+    }
+
+    /// Releases the memory of a node and moves its content out.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `alloc` of this allocator and not
+    /// have been released yet, and no reference to the node may be alive.
+    unsafe fn free(&mut self, ptr: N::Ptr) -> N;
+
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `alloc` of this allocator and not
+    /// have been released yet, and no mutable reference to the node may be
+    /// alive for the returned lifetime.
+    unsafe fn node(&self, ptr: N::Ptr) -> &N;
+
+    /// # Safety
+    ///
+    /// Same as `node`, and no other reference to the node may be alive for
+    /// the returned lifetime.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn node_mut(&self, ptr: N::Ptr) -> &mut N;
+
+    /// Called when a tree allocated by this allocator is dropped, with the
+    /// root and the size of the tree. Returns whether the nodes of the tree
+    /// should be released, which allocators keeping the tree beyond the life
+    /// of the process, like `Mapped`, don't want.
+    fn drop_tree(&mut self, _root: N::Ptr, _size: usize) -> bool {
+        true
+    }
+}
+
+/// Returned by `try_alloc` when the allocator is out of memory, with the node
+/// which could not be allocated.
+pub struct AllocError<N>(N);
+
+impl<N> AllocError<N> {
+    pub fn new(node: N) -> AllocError<N> {
+        AllocError(node)
+    }
+
+    pub fn into_node(self) -> N {
+        self.0
+    }
+}
+
+impl<N> Debug for AllocError<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("AllocError")
+    }
+}
+
+impl<N> Display for AllocError<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("out of memory for a tree node")
+    }
+}
+
+impl<N> Error for AllocError<N> {}
+
+/// A `NodePtr` which is a plain memory address. Layouts using such pointers
+/// work with the allocators of this crate.
+///
+/// # Safety
+///
+/// `into_raw` must return the address passed to `from_raw`, and a pointer
+/// made from a non-null address must not be NIL.
+pub unsafe trait RawNodePtr<N: Node<Ptr = Self>>: NodePtr<N> {
+    fn from_raw(ptr: *mut N) -> Self;
+    fn into_raw(self) -> *mut N;
+}
+
+/// Allocates every node separately from the global allocator. Without the
+/// `alloc` feature, this is only a placeholder for the default allocator.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Heap;
+
+#[cfg(feature = "alloc")]
+unsafe impl<N: Node> NodeAlloc<N> for Heap where N::Ptr: RawNodePtr<N> {
+    fn alloc(&mut self, node: N) -> N::Ptr {
+        N::Ptr::from_raw(Box::into_raw(Box::new(node)))
+    }
+
+    fn try_alloc(&mut self, node: N) -> Result<N::Ptr, AllocError<N>> {
+        let layout = Layout::new::<N>();
+        if layout.size() == 0 {
+            return Ok(self.alloc(node));
+        }
+        // Allocated like `Box::new` does, so the node is released by `free`.
+        let ptr = unsafe { alloc::alloc::alloc(layout) } as *mut N;
+        if ptr.is_null() {
+            return Err(AllocError(node));
+        }
+        unsafe { ptr.write(node) };
+        Ok(N::Ptr::from_raw(ptr))
+    }
+
+    unsafe fn free(&mut self, ptr: N::Ptr) -> N {
+        *Box::from_raw(ptr.into_raw())
+    }
+
+    unsafe fn node(&self, ptr: N::Ptr) -> &N {
+        &*ptr.into_raw()
+    }
+
+    unsafe fn node_mut(&self, ptr: N::Ptr) -> &mut N {
+        &mut *ptr.into_raw()
+    }
+}
+
+#[cfg(feature = "alloc")]
+const MIN_CHUNK: usize = 16;
+
+/// Allocates nodes from chunks owned by a single tree, reusing the slots of
+/// released nodes.
+///
+/// Chunks double in size as the tree grows and are only returned when the
+/// arena is dropped, so building a tree costs a handful of allocations rather
+/// than one per node.
+#[cfg(feature = "alloc")]
+pub struct Arena<N> {
+    chunks: Vec<(*mut MaybeUninit<N>, usize)>,
+    used: usize,
+    free: Vec<*mut N>
+}
+
+#[cfg(feature = "alloc")]
+impl<N> Arena<N> {
+    pub fn new() -> Arena<N> {
+        Arena {
+            chunks: Vec::new(),
+            used: 0,
+            free: Vec::new()
+        }
+    }
+
+    /// The number of node slots in all the chunks.
+    pub fn capacity(&self) -> usize {
+        self.chunks.iter().map(|(_, len)| len).sum()
+    }
+
+    fn next_slot(&mut self) -> Result<*mut N, TryReserveError> {
+        if let Some(slot) = self.free.pop() {
+            return Ok(slot);
+        }
+        match self.chunks.last() {
+            Some((chunk, len)) if self.used < *len => {
+                self.used += 1;
+                Ok(unsafe { chunk.add(self.used - 1) as *mut N })
+            }
+            _ => {
+                // Room for the released slots is reserved along with every
+                // chunk, so `free` never allocates.
+                let mut chunk = Vec::<MaybeUninit<N>>::new();
+                chunk.try_reserve_exact(self.capacity().max(MIN_CHUNK))?;
+                self.free.try_reserve_exact(self.capacity() + chunk.capacity())?;
+                self.chunks.try_reserve(1)?;
+                let mut chunk = ManuallyDrop::new(chunk);
+                self.chunks.push((chunk.as_mut_ptr(), chunk.capacity()));
+                self.used = 1;
+                Ok(chunk.as_mut_ptr() as *mut N)
+            }
+        }
+    }
+}
+
+// The raw pointers only lead into chunks the arena allocated itself and
+// never shares, so sending or sharing the arena sends or shares its nodes.
+#[cfg(feature = "alloc")]
+unsafe impl<N: Send> Send for Arena<N> {}
+#[cfg(feature = "alloc")]
+unsafe impl<N: Sync> Sync for Arena<N> {}
+
+#[cfg(feature = "alloc")]
+impl<N> Default for Arena<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<N> Drop for Arena<N> {
+    fn drop(&mut self) {
+        for (chunk, len) in self.chunks.drain(..) {
+            unsafe { drop(Vec::from_raw_parts(chunk, 0, len)) }
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+unsafe impl<N: Node> NodeAlloc<N> for Arena<N> where N::Ptr: RawNodePtr<N> {
+    /// # Panics
+    ///
+    /// Panics if a new chunk can't be allocated.
+    fn alloc(&mut self, node: N) -> N::Ptr {
+        let slot = self.next_slot().expect("failed to allocate an arena chunk");
+        unsafe { slot.write(node) };
+        N::Ptr::from_raw(slot)
+    }
+
+    fn try_alloc(&mut self, node: N) -> Result<N::Ptr, AllocError<N>> {
+        match self.next_slot() {
+            Ok(slot) => {
+                unsafe { slot.write(node) };
+                Ok(N::Ptr::from_raw(slot))
+            }
+            Err(_) => Err(AllocError(node))
+        }
+    }
+
+    unsafe fn free(&mut self, ptr: N::Ptr) -> N {
+        let slot = ptr.into_raw();
+        self.free.push(slot);
+        slot.read()
+    }
+
+    unsafe fn node(&self, ptr: N::Ptr) -> &N {
+        &*ptr.into_raw()
+    }
+
+    unsafe fn node_mut(&self, ptr: N::Ptr) -> &mut N {
+        &mut *ptr.into_raw()
+    }
+}
+
+/// Wraps another allocator to account for the memory taken by the nodes of
+/// a tree.
+#[derive(Default)]
+pub struct Counting<A = Heap> {
+    inner: A,
+    bytes: usize,
+    peak_bytes: usize
+}
+
+impl<A> Counting<A> {
+    pub fn new(inner: A) -> Counting<A> {
+        Counting {
+            inner,
+            bytes: 0,
+            peak_bytes: 0
+        }
+    }
+
+    /// The bytes taken by the nodes currently allocated.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// The most bytes ever taken by nodes allocated at the same time.
+    pub fn peak_bytes(&self) -> usize {
+        self.peak_bytes
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    fn add_bytes(&mut self, bytes: usize) {
+        self.bytes += bytes;
+        self.peak_bytes = self.peak_bytes.max(self.bytes);
+    }
+}
+
+unsafe impl<N: Node, A: NodeAlloc<N>> NodeAlloc<N> for Counting<A> {
+    fn alloc(&mut self, node: N) -> N::Ptr {
+        let ptr = self.inner.alloc(node);
+        self.add_bytes(size_of::<N>());
+        ptr
+    }
+
+    fn try_alloc(&mut self, node: N) -> Result<N::Ptr, AllocError<N>> {
+        let ptr = self.inner.try_alloc(node)?;
+        self.add_bytes(size_of::<N>());
+        Ok(ptr)
+    }
+
+    unsafe fn free(&mut self, ptr: N::Ptr) -> N {
+        self.bytes -= size_of::<N>();
+        self.inner.free(ptr)
+    }
+
+    unsafe fn node(&self, ptr: N::Ptr) -> &N {
+        self.inner.node(ptr)
+    }
+
+    unsafe fn node_mut(&self, ptr: N::Ptr) -> &mut N {
+        self.inner.node_mut(ptr)
+    }
+
+    fn drop_tree(&mut self, root: N::Ptr, size: usize) -> bool {
+        self.inner.drop_tree(root, size)
+    }
+}
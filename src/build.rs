@@ -0,0 +1,105 @@
+use alloc::vec::Vec;
+
+use crate::{Node, NodeAlloc, NodePtr, PtrExt, RBTree};
+
+impl<N: Node, A: NodeAlloc<N>> RBTree<N, A> {
+    /// Lists the nodes of the tree in key order.
+    pub(crate) fn in_order(&self) -> Vec<N::Ptr> {
+        let mut nodes = Vec::with_capacity(self.size);
+        let mut stack = Vec::new();
+        let mut ptr = self.root;
+        loop {
+            while !ptr.is_nil() {
+                stack.push(ptr);
+                ptr = ptr.node(&self.alloc).left();
+            }
+            match stack.pop() {
+                Some(top) => {
+                    nodes.push(top);
+                    ptr = top.node(&self.alloc).right();
+                }
+                None => return nodes
+            }
+        }
+    }
+
+    /// Moves nodes which are sorted by key, without duplicates, into a new
+    /// balanced tree in linear time.
+    #[cfg(feature = "serde")]
+    pub(crate) fn from_sorted(nodes: Vec<N>, alloc: A) -> Self {
+        let mut tree = RBTree::with_alloc(alloc);
+        let mut sorted = Sorted::new(&mut tree);
+        for node in nodes {
+            sorted.push(node);
+        }
+        drop(sorted);
+        tree
+    }
+
+    /// Links the given nodes, which must be sorted by key, into a balanced
+    /// tree in linear time, replacing the current content of the tree.
+    ///
+    /// Every level of the result is full except possibly the deepest one, so
+    /// colouring the deepest level red and everything else black satisfies
+    /// the red-black properties without any rotation.
+    pub(crate) fn rebuild(&mut self, nodes: &[N::Ptr]) {
+        let red_depth = (usize::BITS - nodes.len().leading_zeros()).saturating_sub(1) as usize;
+        self.root = Self::build(nodes, 0, red_depth, &self.alloc);
+        self.size = nodes.len();
+        self.near = None;
+    }
+
+    fn build(nodes: &[N::Ptr], depth: usize, red_depth: usize, alloc: &A) -> N::Ptr {
+        if nodes.is_empty() {
+            return N::Ptr::NIL;
+        }
+        let mid = nodes.len() / 2;
+        let ptr = nodes[mid];
+        let left = Self::build(&nodes[..mid], depth + 1, red_depth, alloc);
+        let right = Self::build(&nodes[mid + 1..], depth + 1, red_depth, alloc);
+        let node = ptr.node_mut(alloc);
+        node.set_left(left);
+        node.set_right(right);
+        if depth == red_depth && depth > 0 {
+            node.set_red();
+        } else {
+            node.set_black();
+        }
+        Self::refresh(alloc, ptr);
+        ptr
+    }
+}
+
+/// Allocates nodes which are sorted by key, without duplicates, and links
+/// them into a tree when dropped. The nodes allocated so far end up owned by
+/// the tree even if producing the rest fails or panics.
+#[cfg(any(feature = "serde", feature = "std"))]
+pub(crate) struct Sorted<'a, N: Node, A: NodeAlloc<N>> {
+    tree: &'a mut RBTree<N, A>,
+    nodes: Vec<N::Ptr>
+}
+
+#[cfg(any(feature = "serde", feature = "std"))]
+impl<'a, N: Node, A: NodeAlloc<N>> Sorted<'a, N, A> {
+    pub(crate) fn new(tree: &'a mut RBTree<N, A>) -> Self {
+        Sorted { tree, nodes: Vec::new() }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn last(&self) -> Option<&N> {
+        self.nodes.last().map(|ptr| ptr.node(&self.tree.alloc))
+    }
+
+    pub(crate) fn push(&mut self, node: N) {
+        self.nodes.reserve(1);
+        let ptr = self.tree.alloc.alloc(node);
+        self.nodes.push(ptr);
+    }
+}
+
+#[cfg(any(feature = "serde", feature = "std"))]
+impl<'a, N: Node, A: NodeAlloc<N>> Drop for Sorted<'a, N, A> {
+    fn drop(&mut self) {
+        self.tree.rebuild(&self.nodes);
+    }
+}
@@ -0,0 +1,389 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::error::Error;
+use core::fmt::{self, Debug, Display, Formatter};
+
+use crate::{Heap, Hint, Node, NodeAlloc, NodePtr, PtrExt, RBTree};
+use crate::hint::{Route, Sides};
+
+/// The path from the root to the current node of a cursor. Since nodes have
+/// no parent pointers, the ancestors are kept on this stack instead. An empty
+/// path is the "ghost" position between the last and the first node.
+struct Path<N: Node> {
+    stack: Vec<N::Ptr>
+}
+
+// The pointers are only dereferenced through the tree borrowed by the cursor,
+// which decides whether the cursor can cross threads.
+unsafe impl<N: Node> Send for Path<N> {}
+unsafe impl<N: Node> Sync for Path<N> {}
+
+impl<N: Node> Clone for Path<N> {
+    fn clone(&self) -> Self {
+        Path { stack: self.stack.clone() }
+    }
+}
+
+impl<N: Node> Path<N> {
+    fn new() -> Self {
+        Path { stack: Vec::new() }
+    }
+
+    fn current(&self) -> Option<N::Ptr> {
+        self.stack.last().copied()
+    }
+
+    fn push_left_most<A: NodeAlloc<N>>(&mut self, mut ptr: N::Ptr, alloc: &A) {
+        while !ptr.is_nil() {
+            self.stack.push(ptr);
+            ptr = ptr.node(alloc).left();
+        }
+    }
+
+    fn push_right_most<A: NodeAlloc<N>>(&mut self, mut ptr: N::Ptr, alloc: &A) {
+        while !ptr.is_nil() {
+            self.stack.push(ptr);
+            ptr = ptr.node(alloc).right();
+        }
+    }
+
+    fn seek_front<A: NodeAlloc<N>>(&mut self, tree: &RBTree<N, A>) {
+        self.stack.clear();
+        self.push_left_most(tree.root, &tree.alloc);
+    }
+
+    fn seek_back<A: NodeAlloc<N>>(&mut self, tree: &RBTree<N, A>) {
+        self.stack.clear();
+        self.push_right_most(tree.root, &tree.alloc);
+    }
+
+    // Positions at the first node whose key is not less than `key`.
+    fn seek_ceiling<A: NodeAlloc<N>>(&mut self, tree: &RBTree<N, A>, key: &N::Key) {
+        self.stack.clear();
+        let mut depth = 0;
+        let mut ptr = tree.root;
+        while !ptr.is_nil() {
+            self.stack.push(ptr);
+            let node = ptr.node(&tree.alloc);
+            match node.key().cmp(key) {
+                Ordering::Equal => { return }
+                Ordering::Less => { ptr = node.right() }
+                Ordering::Greater => {
+                    depth = self.stack.len();
+                    ptr = node.left()
+                }
+            }
+        }
+        self.stack.truncate(depth);
+    }
+
+    // Positions at the first node whose key is not `before`, which must hold
+    // for the keys up to some node and for none after it.
+    fn seek_partition<A: NodeAlloc<N>>(&mut self, tree: &RBTree<N, A>, before: &mut impl FnMut(&N::Key) -> bool) {
+        self.stack.clear();
+        let mut depth = 0;
+        let mut ptr = tree.root;
+        while !ptr.is_nil() {
+            self.stack.push(ptr);
+            let node = ptr.node(&tree.alloc);
+            if before(node.key()) {
+                ptr = node.right();
+            } else {
+                depth = self.stack.len();
+                ptr = node.left();
+            }
+        }
+        self.stack.truncate(depth);
+    }
+
+    fn move_next<A: NodeAlloc<N>>(&mut self, tree: &RBTree<N, A>) {
+        let current = match self.current() {
+            Some(current) => current,
+            None => return self.seek_front(tree)
+        };
+        let alloc = &tree.alloc;
+        let right = current.node(alloc).right();
+        if !right.is_nil() {
+            return self.push_left_most(right, alloc);
+        }
+        let mut child = self.stack.pop().unwrap();
+        while let Some(parent) = self.current() {
+            if child.same_as(parent.node(alloc).left(), alloc) {
+                return;
+            }
+            child = self.stack.pop().unwrap();
+        }
+    }
+
+    fn move_prev<A: NodeAlloc<N>>(&mut self, tree: &RBTree<N, A>) {
+        let current = match self.current() {
+            Some(current) => current,
+            None => return self.seek_back(tree)
+        };
+        let alloc = &tree.alloc;
+        let left = current.node(alloc).left();
+        if !left.is_nil() {
+            return self.push_right_most(left, alloc);
+        }
+        let mut child = self.stack.pop().unwrap();
+        while let Some(parent) = self.current() {
+            if child.same_as(parent.node(alloc).right(), alloc) {
+                return;
+            }
+            child = self.stack.pop().unwrap();
+        }
+    }
+
+    // The route from the root to the current node, if any.
+    fn route<A: NodeAlloc<N>>(&self, alloc: &A) -> Route {
+        if self.stack.is_empty() {
+            return Route::NONE;
+        }
+        let mut route = Route::EMPTY;
+        for pair in self.stack.windows(2) {
+            route.push_back(pair[1].same_as(pair[0].node(alloc).right(), alloc));
+        }
+        route
+    }
+}
+
+/// A cursor over the nodes of a `RBTree` in key order.
+///
+/// Besides the nodes, a cursor may point to a "ghost" position between the
+/// last and the first node, where `current` returns `None`. Moving forward
+/// from the ghost goes to the first node, and backward to the last one.
+pub struct Cursor<'a, N: Node, A: NodeAlloc<N> = Heap> {
+    tree: &'a RBTree<N, A>,
+    path: Path<N>
+}
+
+impl<'a, N: Node, A: NodeAlloc<N>> Cursor<'a, N, A> {
+    pub fn current(&self) -> Option<&'a N> {
+        let alloc = &self.tree.alloc;
+        self.path.current().map(|ptr| ptr.node(alloc))
+    }
+
+    pub fn move_next(&mut self) {
+        self.path.move_next(self.tree)
+    }
+
+    pub fn move_prev(&mut self) {
+        self.path.move_prev(self.tree)
+    }
+
+    /// Returns a hint to the current node for `RBTree::insert_with_hint`.
+    pub fn hint(&self) -> Hint {
+        Hint(self.path.route(&self.tree.alloc))
+    }
+}
+
+/// A cursor which can also remove and insert nodes around its position.
+pub struct CursorMut<'a, N: Node, A: NodeAlloc<N> = Heap> {
+    tree: &'a mut RBTree<N, A>,
+    path: Path<N>
+}
+
+impl<'a, N: Node, A: NodeAlloc<N>> CursorMut<'a, N, A> {
+    pub fn current(&self) -> Option<&N> {
+        self.path.current().map(|ptr| ptr.node(&self.tree.alloc))
+    }
+
+    pub fn move_next(&mut self) {
+        self.path.move_next(self.tree)
+    }
+
+    pub fn move_prev(&mut self) {
+        self.path.move_prev(self.tree)
+    }
+
+    /// Returns a hint to the current node for `RBTree::insert_with_hint`.
+    pub fn hint(&self) -> Hint {
+        Hint(self.path.route(&self.tree.alloc))
+    }
+
+    /// Removes the current node, moves the cursor to the next one and
+    /// returns the removed node. Returns `None` at the ghost position.
+    pub fn remove_current(&mut self) -> Option<N> {
+        let removed = self.unlink_current();
+        if removed.is_nil() {
+            None
+        } else {
+            Some(RBTree::release(&mut self.tree.alloc, removed))
+        }
+    }
+
+    pub(crate) fn unlink_current(&mut self) -> N::Ptr {
+        if self.path.current().is_none() {
+            return N::Ptr::NIL;
+        }
+        let mut next = self.path.clone();
+        next.move_next(self.tree);
+        // The nodes above the next one, with the side of each it is on, stay
+        // known through the rotations and tell the way back down to it.
+        let alloc = &self.tree.alloc;
+        let mut sides = Sides::new();
+        for pair in next.stack.windows(2) {
+            sides.push(pair[0], pair[1].same_as(pair[0].node(alloc).right(), alloc));
+        }
+        let next = next.current();
+        let mut directions = self.path.route(alloc).directions();
+        let removed = self.tree.unlink_with(&mut |_| directions(), &mut |alloc, up, down| match next {
+            // A node raised right above the next one has it as a child.
+            Some(next) if down.same_as(next, alloc) => sides.push(up, down.same_as(up.node(alloc).right(), alloc)),
+            _ => sides.raised(alloc, up, down)
+        });
+        self.path.stack.clear();
+        if let Some(next) = next {
+            let alloc = &self.tree.alloc;
+            let mut ptr = self.tree.root;
+            self.path.stack.push(ptr);
+            while !ptr.same_as(next, alloc) {
+                let right = sides.side(alloc, ptr).expect("a node above the next one is not known");
+                ptr = if right { ptr.node(alloc).right() } else { ptr.node(alloc).left() };
+                self.path.stack.push(ptr);
+            }
+        }
+        removed
+    }
+
+    /// Inserts a node, and moves the cursor to it. A node which belongs right
+    /// next to the current one is linked following the path of the cursor,
+    /// which skips key comparisons along that path. If a node with
+    /// the same key is already in the tree, it is replaced and returned.
+    pub fn insert_with_hint(&mut self, node: N) -> Option<N> {
+        let replaced = self.tree.insert_with_hint(self.hint(), node);
+        let stack = &mut self.path.stack;
+        stack.clear();
+        self.tree.follow(self.tree.last, |ptr| stack.push(ptr));
+        replaced
+    }
+
+    /// Inserts a node right after the current one, or at the front if the
+    /// cursor is at the ghost position. The cursor does not move.
+    ///
+    /// The node is linked into the empty slot next to the current one,
+    /// following the path of the cursor without comparing keys on the way.
+    /// Fails, handing the node back, if its key does not fall strictly
+    /// between the current key and the next one.
+    pub fn insert_after(&mut self, node: N) -> Result<(), UnorderedKeyError<N>> {
+        let current = self.path.current();
+        let mut next = self.path.clone();
+        next.move_next(self.tree);
+        let alloc = &self.tree.alloc;
+        if let Some(current) = current {
+            if current.node(alloc).key() >= node.key() {
+                return Err(UnorderedKeyError(node));
+            }
+        }
+        if let Some(next) = next.current() {
+            if next.node(alloc).key() <= node.key() {
+                return Err(UnorderedKeyError(node));
+            }
+        }
+        let route = match current {
+            Some(current) => RBTree::route_beside(alloc, self.path.route(alloc), current, true),
+            None => {
+                let mut route = Route::EMPTY;
+                let mut ptr = self.tree.root;
+                while !ptr.is_nil() {
+                    route.push_back(false);
+                    ptr = ptr.node(alloc).left();
+                }
+                Some(route)
+            }
+        };
+        let replaced = self.tree.insert_near(node, route);
+        debug_assert!(replaced.is_none());
+        // The rotations may have moved the current node, which is the one
+        // before the inserted node.
+        let stack = &mut self.path.stack;
+        stack.clear();
+        self.tree.follow(self.tree.last, |ptr| stack.push(ptr));
+        self.path.move_prev(self.tree);
+        Ok(())
+    }
+}
+
+/// Returned by `CursorMut::insert_after` with the node to insert when its key
+/// would break the order of the tree at the cursor position.
+pub struct UnorderedKeyError<N>(N);
+
+impl<N> UnorderedKeyError<N> {
+    pub fn into_node(self) -> N {
+        self.0
+    }
+}
+
+impl<N> Debug for UnorderedKeyError<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("UnorderedKeyError")
+    }
+}
+
+impl<N> Display for UnorderedKeyError<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("key is not properly ordered relative to the cursor position")
+    }
+}
+
+impl<N> Error for UnorderedKeyError<N> {}
+
+impl<N: Node, A: NodeAlloc<N>> RBTree<N, A> {
+    pub fn cursor_front(&self) -> Cursor<'_, N, A> {
+        let mut path = Path::new();
+        path.seek_front(self);
+        Cursor { tree: self, path }
+    }
+
+    pub fn cursor_back(&self) -> Cursor<'_, N, A> {
+        let mut path = Path::new();
+        path.seek_back(self);
+        Cursor { tree: self, path }
+    }
+
+    /// Returns a cursor at the node with the given key, or at the first node
+    /// with a greater key if there is no such node.
+    pub fn cursor_at(&self, key: &N::Key) -> Cursor<'_, N, A> {
+        let mut path = Path::new();
+        path.seek_ceiling(self, key);
+        Cursor { tree: self, path }
+    }
+
+    /// Returns a cursor at the first node whose key is not `before`. The keys
+    /// which are `before` must all come ahead of those which are not.
+    pub(crate) fn cursor_partition(&self, mut before: impl FnMut(&N::Key) -> bool) -> Cursor<'_, N, A> {
+        let mut path = Path::new();
+        path.seek_partition(self, &mut before);
+        Cursor { tree: self, path }
+    }
+
+    /// Returns a cursor at the first node which can modify the tree. It
+    /// borrows the tree mutably, so no other cursor can be held across it:
+    ///
+    /// ```compile_fail
+    /// use red_black::{KeyValue, RBTree};
+    ///
+    /// let mut tree = RBTree::new();
+    /// tree.insert_owned(KeyValue::new(1, "one"));
+    /// let cursor = tree.cursor_front();
+    /// tree.cursor_front_mut().remove_current();
+    /// assert_eq!("one", *cursor.current().unwrap().value());
+    /// ```
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, N, A> {
+        let mut path = Path::new();
+        path.seek_front(self);
+        CursorMut { tree: self, path }
+    }
+
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, N, A> {
+        let mut path = Path::new();
+        path.seek_back(self);
+        CursorMut { tree: self, path }
+    }
+
+    pub fn cursor_at_mut(&mut self, key: &N::Key) -> CursorMut<'_, N, A> {
+        let mut path = Path::new();
+        path.seek_ceiling(self, key);
+        CursorMut { tree: self, path }
+    }
+}
@@ -0,0 +1,185 @@
+use alloc::vec::Vec;
+
+use crate::hint::Route;
+use crate::{Heap, Node, NodeAlloc, NodePtr, PtrExt, RBTree};
+use crate::kv::{Key, KeyValue, Value};
+
+/// An entry of a `HandleMap`, with the index of its handle slot next to the
+/// value.
+pub type HandleEntry<K, V> = KeyValue<K, (V, usize)>;
+
+/// Refers to an entry of a `HandleMap` until the entry is removed or
+/// replaced. Stale handles are detected, not dereferenced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: usize,
+    generation: u32
+}
+
+// A node of the map, or NIL while the slot is free. The generation counts
+// the entries which have held the slot, so that handles to the earlier ones
+// don't match. A slot whose generation runs out is retired rather than
+// wrapped around to match handles from long ago.
+struct HandleSlot<P> {
+    ptr: P,
+    generation: u32
+}
+
+// The last generation of a slot.
+const LAST_GENERATION: u32 = u32::MAX;
+
+// A slot only names a node of the tree in the same map, and is only read
+// through a borrow of the map, so the slots add nothing to what the tree
+// allows.
+unsafe impl<P> Send for HandleSlot<P> {}
+unsafe impl<P> Sync for HandleSlot<P> {}
+
+/// A map whose entries can be reached through a `Handle` in constant time,
+/// without keeping a copy of the key.
+///
+/// Nodes stay in place while they are in the tree, so every handle leads to
+/// a slot holding the pointer to its node.
+pub struct HandleMap<K: Key, V: Value, A: NodeAlloc<HandleEntry<K, V>> = Heap> {
+    pub(crate) tree: RBTree<HandleEntry<K, V>, A>,
+    slots: Vec<HandleSlot<<HandleEntry<K, V> as Node>::Ptr>>,
+    free: Vec<usize>,
+    last_generation: u32
+}
+
+impl<K: Key, V: Value, A: NodeAlloc<HandleEntry<K, V>> + Default> Default for HandleMap<K, V, A> {
+    fn default() -> Self {
+        Self::with_alloc(A::default())
+    }
+}
+
+impl<K: Key, V: Value> HandleMap<K, V> {
+    pub fn new() -> HandleMap<K, V> {
+        Self::with_alloc(Heap)
+    }
+}
+
+impl<K: Key, V: Value, A: NodeAlloc<HandleEntry<K, V>>> HandleMap<K, V, A> {
+    pub fn with_alloc(alloc: A) -> HandleMap<K, V, A> {
+        Self::with_last_generation(alloc, LAST_GENERATION)
+    }
+
+    // Retires slots after fewer generations, so that tests can run one out.
+    pub(crate) fn with_last_generation(alloc: A, last_generation: u32) -> HandleMap<K, V, A> {
+        HandleMap { tree: RBTree::with_alloc(alloc), slots: Vec::new(), free: Vec::new(), last_generation }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts an entry and returns a handle to it. An entry with the same
+    /// key is replaced, and its handles go stale.
+    pub fn insert_handle(&mut self, key: K, value: V) -> Handle {
+        // The slot is taken once the entry is in, in case a comparison panics.
+        let index = self.free.last().copied().unwrap_or(self.slots.len());
+        let mut inserted = NodePtr::NIL;
+        let replaced = self.tree.insert_with(
+            KeyValue::new(key, (value, index)),
+            &mut |current, node| current.key().cmp(node.key()),
+            &mut |alloc, node| {
+                inserted = alloc.alloc(node);
+                Ok(inserted)
+            });
+        if index == self.slots.len() {
+            self.slots.push(HandleSlot { ptr: NodePtr::NIL, generation: 0 });
+        } else {
+            self.free.pop();
+        }
+        match replaced {
+            Ok(None) => {}
+            // The entry took the place of the replaced one.
+            Ok(Some(replaced)) => {
+                let replaced = replaced.value().1;
+                inserted = self.slots[replaced].ptr;
+                self.release_slot(replaced);
+            }
+            Err(_) => unreachable!("infallible allocation failed")
+        }
+        self.slots[index].ptr = inserted;
+        Handle { index, generation: self.slots[index].generation }
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&V> {
+        let ptr = self.ptr(handle)?;
+        Some(&ptr.node(&self.tree.alloc).value().0)
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut V> {
+        let ptr = self.ptr(handle)?;
+        Some(&mut ptr.node_mut(&self.tree.alloc).value_mut().0)
+    }
+
+    pub fn key(&self, handle: Handle) -> Option<&K> {
+        let ptr = self.ptr(handle)?;
+        Some(ptr.node(&self.tree.alloc).key())
+    }
+
+    /// Removes the entry of the handle and returns its key and value.
+    ///
+    /// There are no parent links to walk up from the node, so the way down
+    /// to it is found by comparing its key, which costs O(log n) comparisons
+    /// like `remove`. The node is then unlinked along that route without
+    /// comparing keys again.
+    pub fn remove_by_handle(&mut self, handle: Handle) -> Option<(K, V)> {
+        let target = self.ptr(handle)?;
+        let mut directions = self.route_to(target).directions();
+        let removed = self.tree.unlink_by(&mut |_| directions());
+        debug_assert!(!removed.is_nil());
+        let (key, (value, index)) = RBTree::release(&mut self.tree.alloc, removed).into_key_value();
+        self.release_slot(index);
+        Some((key, value))
+    }
+
+    pub fn search(&self, key: &K) -> Option<&V> {
+        self.tree.search(key).map(|node| &node.value().0)
+    }
+
+    /// Returns a handle to the entry with the given key.
+    pub fn handle(&self, key: &K) -> Option<Handle> {
+        let index = self.tree.search(key)?.value().1;
+        Some(Handle { index, generation: self.slots[index].generation })
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (_, (value, index)) = self.tree.remove(key)?.into_key_value();
+        self.release_slot(index);
+        Some(value)
+    }
+
+    fn ptr(&self, handle: Handle) -> Option<<HandleEntry<K, V> as Node>::Ptr> {
+        let slot = self.slots.get(handle.index)?;
+        if slot.generation == handle.generation && !slot.ptr.is_nil() { Some(slot.ptr) } else { None }
+    }
+
+    fn release_slot(&mut self, index: usize) {
+        let slot = &mut self.slots[index];
+        slot.ptr = NodePtr::NIL;
+        if slot.generation < self.last_generation {
+            slot.generation += 1;
+            self.free.push(index);
+        }
+    }
+
+    fn route_to(&self, target: <HandleEntry<K, V> as Node>::Ptr) -> Route {
+        let alloc = &self.tree.alloc;
+        let target = target.node(alloc);
+        let mut route = Route::EMPTY;
+        let mut ptr = self.tree.root;
+        while !ptr.is_nil() && !core::ptr::eq(ptr.node(alloc), target) {
+            let node = ptr.node(alloc);
+            let right = node.key() < target.key();
+            route.push_back(right);
+            ptr = if right { node.right() } else { node.left() };
+        }
+        route
+    }
+}
@@ -0,0 +1,457 @@
+use core::cmp::Ordering;
+
+use crate::{Node, NodeAlloc, NodePtr, PtrExt, RBTree};
+#[cfg(feature = "alloc")]
+use crate::{AllocError, AllocFn};
+
+/// The directions from the root to a node, one bit per level with the top
+/// level in the highest used bit. A set bit goes right.
+///
+/// A route only holds directions, so following a stale one is harmless: it
+/// leads to some other node or ends early.
+#[derive(Clone, Copy)]
+pub(crate) struct Route {
+    bits: u128,
+    len: u8
+}
+
+impl Route {
+    pub(crate) const EMPTY: Route = Route { bits: 0, len: 0 };
+    // No route. A tree of less than 2^64 nodes is less than 128 levels deep,
+    // so every route fits.
+    pub(crate) const NONE: Route = Route { bits: 0, len: u8::MAX };
+
+    pub(crate) fn is_none(&self) -> bool {
+        self.len > 128
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Whether the route goes right at the given depth.
+    pub(crate) fn step(&self, depth: usize) -> bool {
+        debug_assert!(depth < self.len(), "step {} past the end of a route of {}", depth, self.len());
+        self.bits >> (self.len as usize - 1 - depth) & 1 == 1
+    }
+
+    fn set_step(&mut self, depth: usize, right: bool) {
+        let bit = 1 << (self.len as usize - 1 - depth);
+        if right { self.bits |= bit } else { self.bits &= !bit }
+    }
+
+    pub(crate) fn push_front(&mut self, right: bool) {
+        if self.len >= 128 {
+            *self = Route::NONE;
+        } else {
+            self.bits |= (right as u128) << self.len;
+            self.len += 1;
+        }
+    }
+
+    pub(crate) fn push_back(&mut self, right: bool) {
+        if self.len >= 128 {
+            *self = Route::NONE;
+        } else {
+            self.bits = self.bits << 1 | right as u128;
+            self.len += 1;
+        }
+    }
+
+    /// The comparisons which lead a descent along the route: Less to go
+    /// right, Greater to go left, then Equal at its end.
+    pub(crate) fn directions(self) -> impl FnMut() -> Ordering {
+        let mut depth = 0;
+        move || {
+            depth += 1;
+            match depth - 1 {
+                d if d == self.len() => Ordering::Equal,
+                d if self.step(d) => Ordering::Less,
+                _ => Ordering::Greater
+            }
+        }
+    }
+
+    /// A probe for `RBTree::insert_with` which follows the route.
+    pub(crate) fn probe<N>(self) -> impl FnMut(&N, &N) -> Ordering {
+        let mut directions = self.directions();
+        move |_, _| directions()
+    }
+
+    /// Adjusts a route from a red node X through its red child C, on the
+    /// inner side of X, to the rotations which bring C up to the place of
+    /// the parent of X, with X and the parent as its children. The route
+    /// then starts from that place.
+    pub(crate) fn rotate_inner(&mut self) {
+        if self.len == 1 {
+            *self = Route::EMPTY;
+        } else {
+            let first = self.step(1);
+            self.set_step(0, first);
+            self.set_step(1, !first);
+        }
+    }
+}
+
+/// The last node inserted into a tree and its neighbours in key order, NIL
+/// at either end. It is dropped as soon as a node leaves the tree, which
+/// could leave its pointers dangling.
+#[derive(Clone, Copy)]
+pub(crate) struct Near<P> {
+    pub(crate) node: P,
+    pub(crate) prev: P,
+    pub(crate) next: P
+}
+
+impl<N: Node, A: NodeAlloc<N>> RBTree<N, A> {
+    /// Follows `route` to a node, and if `key` belongs right next to it,
+    /// returns the route on to the node with the key, or to the empty slot
+    /// where it belongs. This takes at most two key comparisons.
+    pub(crate) fn route_near(&self, route: Route, key: &N::Key) -> Option<Route> {
+        if route.is_none() {
+            return None;
+        }
+        let alloc = &self.alloc;
+        // The nearest ancestors of the anchor which are before and after it.
+        let mut lower = N::Ptr::NIL;
+        let mut upper = N::Ptr::NIL;
+        let mut anchor = self.root;
+        for depth in 0..route.len() {
+            if anchor.is_nil() {
+                return None;
+            }
+            if route.step(depth) {
+                lower = anchor;
+                anchor = anchor.node(alloc).right();
+            } else {
+                upper = anchor;
+                anchor = anchor.node(alloc).left();
+            }
+        }
+        if anchor.is_nil() {
+            return None;
+        }
+        let (bound, right) = match anchor.node(alloc).key().cmp(key) {
+            Ordering::Equal => return Some(route),
+            Ordering::Less => (upper, true),
+            Ordering::Greater => (lower, false)
+        };
+        // The neighbour of the anchor on the side of `key` is the closest
+        // node of its subtree on that side, or else the bound.
+        let neighbour = self.closest(anchor, right);
+        let neighbour = if neighbour.is_nil() { bound } else { neighbour };
+        if !Self::short_of(alloc, neighbour, key, right) {
+            return None;
+        }
+        Self::route_beside(alloc, route, anchor, right)
+    }
+
+    /// Tells on which side of the last inserted node `key` belongs, if it is
+    /// that node's key or falls between it and one of its neighbours: Less
+    /// if after it, Greater if before it. This takes at most two key
+    /// comparisons.
+    pub(crate) fn side_of_last(&self, key: &N::Key) -> Option<Ordering> {
+        let near = self.near?;
+        let alloc = &self.alloc;
+        let side = near.node.node(alloc).key().cmp(key);
+        let (neighbour, right) = match side {
+            Ordering::Equal => return Some(side),
+            Ordering::Less => (near.next, true),
+            Ordering::Greater => (near.prev, false)
+        };
+        if Self::short_of(alloc, neighbour, key, right) { Some(side) } else { None }
+    }
+
+    /// Returns the route to where a key on the given side of the last
+    /// inserted node belongs, if the route to that node is known.
+    pub(crate) fn route_at_last(&self, side: Ordering) -> Option<Route> {
+        let near = self.near?;
+        if self.last.is_none() {
+            return None;
+        }
+        match side {
+            Ordering::Equal => Some(self.last),
+            _ => Self::route_beside(&self.alloc, self.last, near.node, side == Ordering::Less)
+        }
+    }
+
+    // Whether `key` comes before `neighbour` going right, or after it going
+    // left, or there is no neighbour to pass.
+    fn short_of(alloc: &A, neighbour: N::Ptr, key: &N::Key, right: bool) -> bool {
+        if neighbour.is_nil() {
+            return true;
+        }
+        let order = neighbour.node(alloc).key().cmp(key);
+        order != Ordering::Equal && (order == Ordering::Greater) == right
+    }
+
+    // Extends `route`, which leads to `anchor`, to the empty slot right next
+    // to the anchor on the given side.
+    pub(crate) fn route_beside(alloc: &A, mut route: Route, anchor: N::Ptr, right: bool) -> Option<Route> {
+        route.push_back(right);
+        let mut ptr = if right { anchor.node(alloc).right() } else { anchor.node(alloc).left() };
+        while !ptr.is_nil() {
+            route.push_back(!right);
+            ptr = if right { ptr.node(alloc).left() } else { ptr.node(alloc).right() };
+        }
+        if route.is_none() { None } else { Some(route) }
+    }
+
+    /// Returns the closest node to `ptr` within its subtree on the given
+    /// side, or NIL if that side is empty.
+    pub(crate) fn closest(&self, ptr: N::Ptr, right: bool) -> N::Ptr {
+        let alloc = &self.alloc;
+        let mut closest = N::Ptr::NIL;
+        let mut ptr = if right { ptr.node(alloc).right() } else { ptr.node(alloc).left() };
+        while !ptr.is_nil() {
+            closest = ptr;
+            ptr = if right { ptr.node(alloc).left() } else { ptr.node(alloc).right() };
+        }
+        closest
+    }
+
+    /// Lists the nodes on `route`, from the root down.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn follow(&self, route: Route, mut visit: impl FnMut(N::Ptr)) {
+        if route.is_none() {
+            return;
+        }
+        let mut ptr = self.root;
+        for depth in 0..=route.len() {
+            if ptr.is_nil() {
+                return;
+            }
+            visit(ptr);
+            if depth < route.len() {
+                let node = ptr.node(&self.alloc);
+                ptr = if route.step(depth) { node.right() } else { node.left() };
+            }
+        }
+    }
+}
+
+/// A place in a tree to insert next to, taken from a cursor or from the
+/// last insertion into the tree.
+///
+/// A hint only holds the directions to the place, so a hint which outlives
+/// changes to the tree is harmless: it leads somewhere else, and inserting
+/// with it falls back to a search from the root.
+#[derive(Clone, Copy)]
+pub struct Hint(pub(crate) Route);
+
+impl<N: Node, A: NodeAlloc<N>> RBTree<N, A> {
+    /// Returns a hint to the last inserted node, which stays useful until a
+    /// node leaves the tree.
+    pub fn last_hint(&self) -> Hint {
+        Hint(self.last_route())
+    }
+
+    /// Inserts the node like `insert_owned`, but places it right away if its
+    /// key belongs next to the node `hint` leads to, which takes at most two
+    /// key comparisons. The route of the hint is followed from the root
+    /// without comparing keys on the way.
+    pub fn insert_with_hint(&mut self, hint: Hint, node: N) -> Option<N> {
+        let route = self.route_near(hint.0, node.key());
+        self.insert_near(node, route)
+    }
+
+    /// The route to the last inserted node, or NONE if it is not known.
+    pub(crate) fn last_route(&self) -> Route {
+        #[cfg(feature = "alloc")]
+        if let (Some(near), Some(last)) = (self.near, self.spine.last()) {
+            if last.same_as(near.node, &self.alloc) {
+                let mut route = Route::EMPTY;
+                for pair in self.spine.windows(2) {
+                    route.push_back(pair[1].same_as(pair[0].node(&self.alloc).right(), &self.alloc));
+                }
+                return route;
+            }
+        }
+        if self.near.is_some() { self.last } else { Route::NONE }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<N: Node, A: NodeAlloc<N>> RBTree<N, A> {
+    // Makes sure the spine leads down to the last inserted node, finding it
+    // again from the route to that node if need be, and returns whether it
+    // does.
+    pub(crate) fn follow_spine(&mut self) -> bool {
+        let Some(near) = self.near else {
+            return false;
+        };
+        if self.spine.is_empty() {
+            let mut spine = core::mem::take(&mut self.spine);
+            self.follow(self.last, |ptr| spine.push(ptr));
+            self.spine = spine;
+        }
+        match self.spine.last() {
+            Some(last) if last.same_as(near.node, &self.alloc) => true,
+            _ => {
+                self.spine.clear();
+                false
+            }
+        }
+    }
+
+    // Inserts a node on the given side of the last inserted one, as told by
+    // `side_of_last`, and rebalances up the spine, which the new node then
+    // ends. Appending in order finds no nodes after the last one, so the
+    // node is linked right below it and only the rebalancing climbs, which
+    // is amortized O(1).
+    pub(crate) fn insert_at_last(&mut self, mut node: N, side: Ordering, alloc_fn: &mut AllocFn<'_, N, A>) -> Result<Option<N>, AllocError<N>> {
+        let near = self.near.expect("inserting next to no last node");
+        if side == Ordering::Equal {
+            let replaced = Self::replace(near.node.node_mut(&self.alloc), node);
+            self.refresh_spine();
+            return Ok(Some(replaced));
+        }
+        node.set_left(N::Ptr::NIL);
+        node.set_right(N::Ptr::NIL);
+        node.set_red();
+        let ptr = alloc_fn(&mut self.alloc, node)?;
+        Self::refresh(&self.alloc, ptr);
+        let alloc = &self.alloc;
+        let right = side == Ordering::Less;
+        let mut parent = near.node;
+        let mut at_right = right;
+        let mut child = if right { parent.node(alloc).right() } else { parent.node(alloc).left() };
+        while !child.is_nil() {
+            self.spine.push(child);
+            parent = child;
+            at_right = !right;
+            child = if right { child.node(alloc).left() } else { child.node(alloc).right() };
+        }
+        if at_right {
+            parent.node_mut(alloc).set_right(ptr);
+        } else {
+            parent.node_mut(alloc).set_left(ptr);
+        }
+        self.spine.push(ptr);
+        self.size += 1;
+        self.last = Route::NONE;
+        self.near = Some(if right {
+            Near { node: ptr, prev: near.node, next: near.next }
+        } else {
+            Near { node: ptr, prev: near.prev, next: near.node }
+        });
+        self.repair_spine();
+        self.refresh_spine();
+        Ok(None)
+    }
+
+    // Restores the colours after the red node ending the spine was linked,
+    // climbing the spine and keeping it on the way to that node through the
+    // rotations.
+    fn repair_spine(&mut self) {
+        let alloc = &self.alloc;
+        let spine = &mut self.spine;
+        let mut at = spine.len() - 1;
+        loop {
+            if at == 0 {
+                spine[0].node_mut(alloc).set_black();
+                return;
+            }
+            let parent = spine[at - 1];
+            if parent.is_black(alloc) {
+                return;
+            }
+            // A red parent is not the root, so there is a grandparent.
+            let grand = spine[at - 2];
+            let parent_left = parent.same_as(grand.node(alloc).left(), alloc);
+            let uncle = if parent_left { grand.node(alloc).right() } else { grand.node(alloc).left() };
+            if uncle.is_red(alloc) {
+                parent.node_mut(alloc).set_black();
+                uncle.node_mut(alloc).set_black();
+                grand.node_mut(alloc).set_red();
+                at -= 2;
+                continue;
+            }
+            let me = spine[at];
+            let me_left = me.same_as(parent.node(alloc).left(), alloc);
+            let above = if at > 2 { Some(spine[at - 3]) } else { None };
+            let grand_left = above.map(|above| grand.same_as(above.node(alloc).left(), alloc));
+            let top = if parent_left == me_left {
+                spine.remove(at - 2);
+                if parent_left { Self::rotated_right(alloc, grand) } else { Self::rotated_left(alloc, grand) }
+            } else {
+                // `me` comes up above both, and the spine goes on below it
+                // through whichever of them took its child on the way.
+                let below_left = spine.get(at + 1).map(|below| below.same_as(me.node(alloc).left(), alloc));
+                let top = if parent_left {
+                    grand.node_mut(alloc).set_left(Self::rotated_left(alloc, parent));
+                    Self::rotated_right(alloc, grand)
+                } else {
+                    grand.node_mut(alloc).set_right(Self::rotated_right(alloc, parent));
+                    Self::rotated_left(alloc, grand)
+                };
+                let mid = below_left.map(|left| if left { top.node(alloc).left() } else { top.node(alloc).right() });
+                spine.splice(at - 2..=at, core::iter::once(top).chain(mid));
+                top
+            };
+            top.node_mut(alloc).set_black();
+            grand.node_mut(alloc).set_red();
+            match (above, grand_left) {
+                (Some(above), Some(true)) => above.node_mut(alloc).set_left(top),
+                (Some(above), _) => above.node_mut(alloc).set_right(top),
+                (None, _) => self.root = top
+            }
+            return;
+        }
+    }
+
+    // Recomputes the caches of the nodes on the spine, from the bottom up.
+    fn refresh_spine(&self) {
+        if N::AUGMENTED {
+            for &ptr in self.spine.iter().rev() {
+                Self::refresh(&self.alloc, ptr);
+            }
+        }
+    }
+}
+
+// A tree of less than 2^64 nodes is less than 128 levels deep, and an unlink
+// raises at most four nodes: one swap, then up to three rotations.
+const SIDES: usize = 128 + 4;
+
+/// The nodes known to be before or after an empty slot of a tree. They
+/// start as the nodes on the way down to the slot, and once the tree is
+/// rebalanced, tell the way down again without comparing keys.
+pub(crate) struct Sides<N: Node> {
+    nodes: [(N::Ptr, bool); SIDES],
+    len: usize
+}
+
+impl<N: Node> Sides<N> {
+    pub(crate) fn new() -> Self {
+        Sides { nodes: [(N::Ptr::NIL, false); SIDES], len: 0 }
+    }
+
+    /// Records that the slot is right of `ptr`, or left of it.
+    pub(crate) fn push(&mut self, ptr: N::Ptr, right: bool) {
+        self.nodes[self.len] = (ptr, right);
+        self.len += 1;
+    }
+
+    /// The nodes pushed so far, with the last pushed first.
+    pub(crate) fn rev(&self) -> impl Iterator<Item = (N::Ptr, bool)> + '_ {
+        self.nodes[..self.len].iter().rev().copied()
+    }
+
+    /// Whether the slot is right of `ptr`, if that is known.
+    pub(crate) fn side<A: NodeAlloc<N>>(&self, alloc: &A, ptr: N::Ptr) -> Option<bool> {
+        let node = ptr.node(alloc);
+        self.rev()
+            .find(|(known, _)| core::ptr::eq(known.node(alloc), node))
+            .map(|(_, right)| right)
+    }
+
+    /// Learns of `up` moving up above `down`. Every node above the slot is
+    /// known, so if `up` is not, the slot is not below it, and so not between
+    /// the two: it is on the same side of both.
+    pub(crate) fn raised<A: NodeAlloc<N>>(&mut self, alloc: &A, up: N::Ptr, down: N::Ptr) {
+        if let (None, Some(right)) = (self.side(alloc, up), self.side(alloc, down)) {
+            self.push(up, right);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use core::cell::UnsafeCell;
+use core::fmt::Debug;
+use core::mem::ManuallyDrop;
+
+use crate::{AllocError, Node, NodeAlloc, NodePtr, RBTree, SetKey};
+
+/// An unsigned integer used as the index of a node in an `Inline` allocator.
+/// The largest value of the type is NIL, so `u8` indexes up to 255 nodes and
+/// `u16` up to 65535.
+///
+/// # Safety
+///
+/// `from_index` must be the inverse of `index` for every value below `NIL`.
+pub unsafe trait SlotIndex: Copy + Eq {
+    const NIL: Self;
+    const MAX_CAPACITY: usize;
+
+    fn from_index(index: usize) -> Self;
+    fn index(self) -> usize;
+}
+
+macro_rules! slot_index {
+    ($($t:ty),*) => {
+        $(unsafe impl SlotIndex for $t {
+            const NIL: Self = <$t>::MAX;
+            const MAX_CAPACITY: usize = <$t>::MAX as usize;
+
+            fn from_index(index: usize) -> Self {
+                index as $t
+            }
+
+            fn index(self) -> usize {
+                self as usize
+            }
+        })*
+    }
+}
+
+slot_index!(u8, u16, u32);
+
+/// A key value node linked by indices into the array of an `Inline`
+/// allocator, which is as small as a node can be without packing.
+pub struct StaticNode<K, V, I = u16> {
+    left: I,
+    right: I,
+    black: bool,
+    key: K,
+    value: V
+}
+
+impl<K, V, I: SlotIndex> StaticNode<K, V, I> {
+    pub fn new(key: K, value: V) -> StaticNode<K, V, I> {
+        StaticNode {
+            left: I::NIL,
+            right: I::NIL,
+            black: false,
+            key,
+            value
+        }
+    }
+
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+}
+
+impl<K: Clone, V: Clone, I: SlotIndex> Clone for StaticNode<K, V, I> {
+    fn clone(&self) -> Self {
+        StaticNode::new(self.key.clone(), self.value.clone())
+    }
+}
+
+unsafe impl<K: Ord + Debug, V, I: SlotIndex> Node for StaticNode<K, V, I> {
+    type Key = K;
+    type Ptr = I;
+
+    fn left(&self) -> I {
+        self.left
+    }
+
+    fn set_left(&mut self, ptr: I) {
+        self.left = ptr
+    }
+
+    fn right(&self) -> I {
+        self.right
+    }
+
+    fn set_right(&mut self, ptr: I) {
+        self.right = ptr
+    }
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn is_black(&self) -> bool {
+        self.black
+    }
+
+    fn set_black(&mut self) {
+        self.black = true
+    }
+
+    fn set_red(&mut self) {
+        self.black = false
+    }
+}
+
+impl<K: Ord + Debug, V, I: SlotIndex> SetKey for StaticNode<K, V, I> {
+    fn set_key(&mut self, key: Self::Key) -> Self::Key {
+        core::mem::replace(&mut self.key, key)
+    }
+}
+
+impl<K: Ord + Debug, V, I: SlotIndex> NodePtr<StaticNode<K, V, I>> for I {
+    const NIL: Self = <I as SlotIndex>::NIL;
+
+    fn is_nil(&self) -> bool {
+        *self == <I as SlotIndex>::NIL
+    }
+}
+
+// A node, or the index of the next released slot.
+union Slot<N: Node> {
+    node: ManuallyDrop<N>,
+    next: N::Ptr
+}
+
+/// Allocates nodes from an array of `CAP` slots within the allocator itself,
+/// so a tree needs no heap at all, and can be moved or placed in a static.
+///
+/// Nodes are addressed by their index in the array, so the size of their
+/// links is chosen with the index type of the node, e.g. `u8` for up to 255
+/// nodes.
+pub struct Inline<N: Node, const CAP: usize> {
+    slots: [UnsafeCell<Slot<N>>; CAP],
+    used: usize,
+    free: N::Ptr
+}
+
+/// A tree of at most `CAP` key value nodes, stored inline.
+///
+/// The index type `I` is not chosen from `CAP`, as stable Rust can't pick a
+/// type from the value of a const parameter. It is `u16` by default, and
+/// `u8` has to be named to halve the links for capacities up to 255, e.g.
+/// `StaticRBTree<K, V, 64, u8>`. A type too small for `CAP` fails to compile:
+///
+/// ```compile_fail
+/// use red_black::StaticRBTree;
+///
+/// let tree: StaticRBTree<u32, u32, 300, u8> = StaticRBTree::default();
+/// ```
+pub type StaticRBTree<K, V, const CAP: usize, I = u16> = RBTree<StaticNode<K, V, I>, Inline<StaticNode<K, V, I>, CAP>>;
+
+// The cells are only what lets `node_mut` write through `&self`, which the
+// tree only calls while it is borrowed mutably, and a free slot holds an
+// index rather than a pointer, whatever the type of `N::Ptr`.
+unsafe impl<N: Node + Send, const CAP: usize> Send for Inline<N, CAP> {}
+unsafe impl<N: Node + Sync, const CAP: usize> Sync for Inline<N, CAP> {}
+
+impl<N: Node, const CAP: usize> Inline<N, CAP> where N::Ptr: SlotIndex {
+    // Evaluated for every capacity a tree is created with, so an index type
+    // which can't address all the slots fails to compile.
+    const FITS: () = assert!(CAP <= <N::Ptr as SlotIndex>::MAX_CAPACITY, "capacity out of the range of the index type");
+
+    pub const fn new() -> Inline<N, CAP> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::FITS;
+        Inline {
+            slots: [const { UnsafeCell::new(Slot { next: <N::Ptr as SlotIndex>::NIL }) }; CAP],
+            used: 0,
+            free: <N::Ptr as SlotIndex>::NIL
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        CAP
+    }
+
+    fn slot(&self, ptr: N::Ptr) -> *mut Slot<N> {
+        self.slots[ptr.index()].get()
+    }
+}
+
+impl<N: Node, const CAP: usize> Default for Inline<N, CAP> where N::Ptr: SlotIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<N: Node, const CAP: usize> NodeAlloc<N> for Inline<N, CAP> where N::Ptr: SlotIndex {
+    /// # Panics
+    ///
+    /// Panics if all the slots are taken.
+    fn alloc(&mut self, node: N) -> N::Ptr {
+        match self.try_alloc(node) {
+            Ok(ptr) => ptr,
+            Err(_) => panic!("inline allocator is full")
+        }
+    }
+
+    fn try_alloc(&mut self, node: N) -> Result<N::Ptr, AllocError<N>> {
+        let ptr = if self.free != <N::Ptr as SlotIndex>::NIL {
+            let ptr = self.free;
+            self.free = unsafe { (*self.slot(ptr)).next };
+            ptr
+        } else if self.used < CAP {
+            self.used += 1;
+            N::Ptr::from_index(self.used - 1)
+        } else {
+            return Err(AllocError::new(node));
+        };
+        unsafe { *self.slot(ptr) = Slot { node: ManuallyDrop::new(node) } };
+        Ok(ptr)
+    }
+
+    unsafe fn free(&mut self, ptr: N::Ptr) -> N {
+        let slot = self.slot(ptr);
+        let node = ManuallyDrop::take(&mut (*slot).node);
+        *slot = Slot { next: self.free };
+        self.free = ptr;
+        node
+    }
+
+    unsafe fn node(&self, ptr: N::Ptr) -> &N {
+        &(*self.slot(ptr)).node
+    }
+
+    unsafe fn node_mut(&self, ptr: N::Ptr) -> &mut N {
+        &mut (*self.slot(ptr)).node
+    }
+}
@@ -0,0 +1,264 @@
+use core::mem;
+use core::ops::{Bound, RangeBounds};
+
+use crate::hint::Route;
+use crate::{Detached, Node, NodeAlloc, NodePtr, PtrExt, RBTree};
+
+/// A detached subtree with its black height: the number of black nodes on
+/// every path from its root down to a leaf. Unlike in a tree, the root may be
+/// red.
+#[derive(Clone, Copy)]
+pub(crate) struct Subtree<P> {
+    pub(crate) root: P,
+    pub(crate) height: usize
+}
+
+impl<N: Node, A: NodeAlloc<N>> RBTree<N, A> {
+    /// Moves all nodes out of the tree, leaving it empty.
+    pub(crate) fn take_subtree(&mut self) -> Subtree<N::Ptr> {
+        let root = mem::replace(&mut self.root, N::Ptr::NIL);
+        self.size = 0;
+        self.last = Route::NONE;
+        self.near = None;
+        let mut height = 0;
+        let mut ptr = root;
+        while !ptr.is_nil() {
+            height += ptr.is_black(&self.alloc) as usize;
+            ptr = ptr.node(&self.alloc).left();
+        }
+        Subtree { root, height }
+    }
+
+    /// Makes `tree`, which holds `size` nodes, the content of an empty tree.
+    pub(crate) fn put_subtree(&mut self, tree: Subtree<N::Ptr>, size: usize) {
+        debug_assert!(self.root.is_nil());
+        if !tree.root.is_nil() {
+            tree.root.node_mut(&self.alloc).set_black();
+        }
+        self.root = tree.root;
+        self.size = size;
+        self.last = Route::NONE;
+        self.near = None;
+    }
+
+    /// Joins `left`, the detached node `mid` and `right`, whose nodes are in
+    /// this order, into one subtree. This takes time proportional to the
+    /// difference of their heights.
+    pub(crate) fn join(alloc: &A, left: Subtree<N::Ptr>, mid: N::Ptr, right: Subtree<N::Ptr>) -> Subtree<N::Ptr> {
+        if left.height > right.height {
+            let root = Self::join_right(alloc, left, mid, right);
+            if root.is_red(alloc) && root.node(alloc).right().is_red(alloc) {
+                root.node_mut(alloc).set_black();
+                return Subtree { root, height: left.height + 1 };
+            }
+            Subtree { root, height: left.height }
+        } else if right.height > left.height {
+            let root = Self::join_left(alloc, left, mid, right);
+            if root.is_red(alloc) && root.node(alloc).left().is_red(alloc) {
+                root.node_mut(alloc).set_black();
+                return Subtree { root, height: right.height + 1 };
+            }
+            Subtree { root, height: right.height }
+        } else if left.root.is_black(alloc) && right.root.is_black(alloc) {
+            mid.node_mut(alloc).set_red();
+            Self::link(alloc, left.root, mid, right.root);
+            Subtree { root: mid, height: left.height }
+        } else {
+            mid.node_mut(alloc).set_black();
+            Self::link(alloc, left.root, mid, right.root);
+            Subtree { root: mid, height: left.height + 1 }
+        }
+    }
+
+    // Descends the right spine of the higher `left` to a black node as high
+    // as `right`, and puts `mid` in its place, with it and `right` as the
+    // children. A red `mid` below a red node is rotated up on the way back,
+    // except at the root, which `join` blackens.
+    fn join_right(alloc: &A, left: Subtree<N::Ptr>, mid: N::Ptr, right: Subtree<N::Ptr>) -> N::Ptr {
+        let top = left.root;
+        if top.is_black(alloc) && left.height == right.height {
+            mid.node_mut(alloc).set_red();
+            Self::link(alloc, top, mid, right.root);
+            return mid;
+        }
+        let below = Subtree {
+            root: top.node(alloc).right(),
+            height: left.height - top.is_black(alloc) as usize
+        };
+        let joined = Self::join_right(alloc, below, mid, right);
+        top.node_mut(alloc).set_right(joined);
+        Self::refresh(alloc, top);
+        let outer = joined.node(alloc).right();
+        if top.is_black(alloc) && joined.is_red(alloc) && outer.is_red(alloc) {
+            outer.node_mut(alloc).set_black();
+            return Self::rotated_left(alloc, top);
+        }
+        top
+    }
+
+    fn join_left(alloc: &A, left: Subtree<N::Ptr>, mid: N::Ptr, right: Subtree<N::Ptr>) -> N::Ptr {
+        let top = right.root;
+        if top.is_black(alloc) && right.height == left.height {
+            mid.node_mut(alloc).set_red();
+            Self::link(alloc, left.root, mid, top);
+            return mid;
+        }
+        let below = Subtree {
+            root: top.node(alloc).left(),
+            height: right.height - top.is_black(alloc) as usize
+        };
+        let joined = Self::join_left(alloc, left, mid, below);
+        top.node_mut(alloc).set_left(joined);
+        Self::refresh(alloc, top);
+        let outer = joined.node(alloc).left();
+        if top.is_black(alloc) && joined.is_red(alloc) && outer.is_red(alloc) {
+            outer.node_mut(alloc).set_black();
+            return Self::rotated_right(alloc, top);
+        }
+        top
+    }
+
+    fn link(alloc: &A, left: N::Ptr, mid: N::Ptr, right: N::Ptr) {
+        let node = mid.node_mut(alloc);
+        node.set_left(left);
+        node.set_right(right);
+        Self::refresh(alloc, mid);
+    }
+
+    /// Splits `tree` into the nodes before the first one for which
+    /// `goes_right` holds, and the rest. `goes_right` is asked once per level
+    /// from the root down, so it can keep track of positions, and must hold
+    /// for every node after one it holds for.
+    pub(crate) fn split(alloc: &A, tree: Subtree<N::Ptr>, goes_right: &mut dyn FnMut(&N) -> bool) -> (Subtree<N::Ptr>, Subtree<N::Ptr>) {
+        let top = tree.root;
+        if top.is_nil() {
+            let empty = Subtree { root: top, height: 0 };
+            return (empty, empty);
+        }
+        let height = tree.height - top.is_black(alloc) as usize;
+        let node = top.node(alloc);
+        let left = Subtree { root: node.left(), height };
+        let right = Subtree { root: node.right(), height };
+        // The top is relinked by the join.
+        if goes_right(node) {
+            let (before, after) = Self::split(alloc, left, goes_right);
+            (before, Self::join(alloc, after, top, right))
+        } else {
+            let (before, after) = Self::split(alloc, right, goes_right);
+            (Self::join(alloc, left, top, before), after)
+        }
+    }
+
+    /// Joins two subtrees whose nodes are in this order, with the last node
+    /// of `left` in the middle.
+    fn join_two(alloc: &A, left: Subtree<N::Ptr>, right: Subtree<N::Ptr>) -> Subtree<N::Ptr> {
+        if left.root.is_nil() {
+            return right;
+        }
+        let (rest, last) = Self::split_last(alloc, left);
+        Self::join(alloc, rest, last, right)
+    }
+
+    fn split_last(alloc: &A, tree: Subtree<N::Ptr>) -> (Subtree<N::Ptr>, N::Ptr) {
+        let top = tree.root;
+        let height = tree.height - top.is_black(alloc) as usize;
+        let node = top.node(alloc);
+        let left = Subtree { root: node.left(), height };
+        if node.right().is_nil() {
+            return (left, top);
+        }
+        let (rest, last) = Self::split_last(alloc, Subtree { root: node.right(), height });
+        (Self::join(alloc, left, top, rest), last)
+    }
+
+    /// Splits `tree` into the nodes before a range, in it and after it, given
+    /// the routes which the bounds of the range take from the root of the
+    /// tree. Nothing is compared on the way, so the nodes can't be left
+    /// scattered by a panic.
+    fn split_range(alloc: &A, tree: Subtree<N::Ptr>, start: Route, end: Route, depth: usize) -> [Subtree<N::Ptr>; 3] {
+        let top = tree.root;
+        if top.is_nil() {
+            let empty = Subtree { root: top, height: 0 };
+            return [empty; 3];
+        }
+        let height = tree.height - top.is_black(alloc) as usize;
+        let node = top.node(alloc);
+        let left = Subtree { root: node.left(), height };
+        let right = Subtree { root: node.right(), height };
+        if start.step(depth) {
+            let [before, inside, after] = Self::split_range(alloc, right, start, end, depth + 1);
+            [Self::join(alloc, left, top, before), inside, after]
+        } else if !end.step(depth) {
+            let [before, inside, after] = Self::split_range(alloc, left, start, end, depth + 1);
+            [before, inside, Self::join(alloc, after, top, right)]
+        } else {
+            // The routes part here, around the first node in the range.
+            let follow = |route: Route| {
+                let mut depth = depth;
+                move |_: &N| {
+                    depth += 1;
+                    !route.step(depth)
+                }
+            };
+            let (before, low) = Self::split(alloc, left, &mut follow(start));
+            let (high, after) = Self::split(alloc, right, &mut follow(end));
+            [before, Self::join(alloc, low, top, high), after]
+        }
+    }
+
+    // The route from the root down to an empty slot, going left at the nodes
+    // for which `goes_left` holds.
+    fn route_by(&self, goes_left: impl Fn(&N::Key) -> bool) -> Route {
+        let mut route = Route::EMPTY;
+        let mut ptr = self.root;
+        while !ptr.is_nil() {
+            let node = ptr.node(&self.alloc);
+            let left = goes_left(node.key());
+            route.push_back(!left);
+            ptr = if left { node.left() } else { node.right() };
+        }
+        route
+    }
+
+    fn count(alloc: &A, ptr: N::Ptr) -> usize {
+        if ptr.is_nil() {
+            return 0;
+        }
+        let node = ptr.node(alloc);
+        1 + Self::count(alloc, node.left()) + Self::count(alloc, node.right())
+    }
+
+    /// Removes the nodes with keys in `range` and returns how many there
+    /// were. The range is split off and the rest joined back in logarithmic
+    /// time, so the cost beyond that is releasing the removed nodes.
+    pub fn delete_range<R: RangeBounds<N::Key>>(&mut self, range: R) -> usize {
+        let (start, end) = (range.start_bound(), range.end_bound());
+        // The routes of the bounds of an empty range may cross, which
+        // `split_range` can't handle.
+        match (start, end) {
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) if s > e => return 0,
+            (Bound::Excluded(s), Bound::Excluded(e)) if s == e => return 0,
+            _ => {}
+        }
+        // Every comparison is made before the tree is taken apart.
+        let start_route = self.route_by(|key| match start {
+            Bound::Included(start) => key >= start,
+            Bound::Excluded(start) => key > start,
+            Bound::Unbounded => true
+        });
+        let end_route = self.route_by(|key| match end {
+            Bound::Included(end) => key > end,
+            Bound::Excluded(end) => key >= end,
+            Bound::Unbounded => false
+        });
+        let size = self.size;
+        let tree = self.take_subtree();
+        let alloc = &self.alloc;
+        let [before, inside, after] = Self::split_range(alloc, tree, start_route, end_route, 0);
+        let removed = Self::count(alloc, inside.root);
+        let rest = Self::join_two(alloc, before, after);
+        self.put_subtree(rest, size - removed);
+        drop(Detached { alloc: &mut self.alloc, root: inside.root });
+        removed
+    }
+}
@@ -1,14 +1,15 @@
-use std::fmt::Debug;
-use std::ptr::null_mut;
+use core::fmt::Debug;
+use core::ptr::null_mut;
 
-use crate::{Node, NodePtr};
+use crate::{Node, NodePtr, RawNodePtr, SetKey};
 
 pub trait Key: Ord + Clone + Debug {}
 impl<T: Ord + Clone + Debug> Key for T {}
 
-pub trait Value: Clone {}
-impl<T: Clone> Value for T {}
+pub trait Value {}
+impl<T> Value for T {}
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum Color {
     BLACK = 0,
@@ -37,62 +38,48 @@ impl<K: Key, V: Value> KeyValue<K, V> {
     pub fn value(&self) -> &V {
         &self.value
     }
-}
 
-impl<K: Key, V: Value> Node for KeyValue<K, V> {
-    type Key = K;
-    type Ptr = KeyValuePtr<K, V>;
-
-    fn new(data: &Self) -> Self::Ptr {
-        use std::alloc::{alloc, Layout};
-
-        unsafe {
-            let layout = Layout::new::<Self>();
-            let ptr = KeyValuePtr(alloc(layout) as *mut Self);
-            let node = &mut (*ptr.0);
-            node.left = KeyValuePtr::NIL;
-            node.right = KeyValuePtr::NIL;
-            node.color = Color::RED;
-            node.key.clone_from(data.key());
-            node.value.clone_from(data.value());
-            return ptr;
-        }
+    #[cfg(feature = "alloc")]
+    pub(crate) fn value_mut(&mut self) -> &mut V {
+        &mut self.value
     }
 
-    fn free(&mut self) {
-        use std::alloc::{dealloc, Layout};
+    #[cfg(feature = "alloc")]
+    pub(crate) fn into_key_value(self) -> (K, V) {
+        (self.key, self.value)
+    }
+}
 
-        unsafe {
-            let layout = Layout::new::<Self>();
-            dealloc(self as *mut Self as *mut u8, layout);
-        }
+impl<K: Key, V: Value + Clone> Clone for KeyValue<K, V> {
+    fn clone(&self) -> Self {
+        KeyValue::new(self.key.clone(), self.value.clone())
     }
+}
 
-    fn left(&self) -> &Self::Ptr {
-        &self.left
+unsafe impl<K: Key, V: Value> Node for KeyValue<K, V> {
+    type Key = K;
+    type Ptr = KeyValuePtr<K, V>;
+
+    fn left(&self) -> Self::Ptr {
+        self.left
     }
 
-    fn left_mut(&mut self) -> &mut Self::Ptr {
-        &mut self.left
+    fn set_left(&mut self, ptr: Self::Ptr) {
+        self.left = ptr
     }
 
-    fn right(&self) -> &Self::Ptr {
-        &self.right
+    fn right(&self) -> Self::Ptr {
+        self.right
     }
 
-    fn right_mut(&mut self) -> &mut Self::Ptr {
-        &mut self.right
+    fn set_right(&mut self, ptr: Self::Ptr) {
+        self.right = ptr
     }
 
     fn key(&self) -> &Self::Key {
         &self.key
     }
 
-    fn update(&mut self, data: &Self) {
-        self.key.clone_from(&data.key);
-        self.value.clone_from(&data.value);
-    }
-
     fn is_black(&self) -> bool {
         self.color == Color::BLACK
     }
@@ -106,11 +93,22 @@ impl<K: Key, V: Value> Node for KeyValue<K, V> {
     }
 }
 
+impl<K: Key, V: Value> SetKey for KeyValue<K, V> {
+    fn set_key(&mut self, key: Self::Key) -> Self::Key {
+        core::mem::replace(&mut self.key, key)
+    }
+}
+
+// The links of a node are only dereferenced by the tree owning it, through
+// its allocator, so they don't affect whether the node can cross threads.
+unsafe impl<K: Key + Send, V: Value + Send> Send for KeyValue<K, V> {}
+unsafe impl<K: Key + Sync, V: Value + Sync> Sync for KeyValue<K, V> {}
+
 pub struct KeyValuePtr<K: Key, V: Value>(*mut KeyValue<K, V>);
 
 impl<K: Key, V: Value> Clone for KeyValuePtr<K, V> {
     fn clone(&self) -> Self {
-        KeyValuePtr(self.0)
+        *self
     }
 }
 
@@ -122,12 +120,14 @@ impl<K: Key, V: Value> NodePtr<KeyValue<K, V>> for KeyValuePtr<K, V> {
     fn is_nil(&self) -> bool {
         self.0.is_null()
     }
+}
 
-    fn node<'a>(&self) -> &'a KeyValue<K, V> {
-        unsafe { &*self.0 }
+unsafe impl<K: Key, V: Value> RawNodePtr<KeyValue<K, V>> for KeyValuePtr<K, V> {
+    fn from_raw(ptr: *mut KeyValue<K, V>) -> Self {
+        KeyValuePtr(ptr)
     }
 
-    fn node_mut<'a>(&self) -> &'a mut KeyValue<K, V> {
-        unsafe { &mut *self.0 }
+    fn into_raw(self) -> *mut KeyValue<K, V> {
+        self.0
     }
 }
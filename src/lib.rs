@@ -1,23 +1,71 @@
-use std::cmp::Ordering;
-use std::fmt::Debug;
-
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use core::cmp::Ordering;
+use core::fmt::Debug;
+
+use hint::{Near, Route, Sides};
+
+mod aggregate;
+mod allocator;
+#[cfg(feature = "alloc")]
+mod build;
+#[cfg(feature = "alloc")]
+mod cursor;
+#[cfg(feature = "alloc")]
+mod handle;
+mod hint;
+mod inline;
+mod join;
 mod kv;
-
-#[cfg(test)]
+#[cfg(feature = "std")]
+mod mapped;
+mod median;
+#[cfg(feature = "alloc")]
+mod multimap;
+#[cfg(feature = "alloc")]
+mod nearest;
+#[cfg(feature = "alloc")]
+mod prefix;
+#[cfg(feature = "alloc")]
+mod range_map;
+#[cfg(feature = "std")]
+mod region;
+#[cfg(feature = "alloc")]
+mod retain;
+#[cfg(feature = "alloc")]
+mod sequence;
+#[cfg(feature = "serde")]
+mod serde_impl;
+#[cfg(feature = "std")]
+mod snapshot;
+
+#[cfg(all(test, feature = "std"))]
 mod tests;
 
-pub trait Node: Sized {
+/// A node of a `RBTree`.
+///
+/// Links are read and written by value, so layouts are free to store them
+/// in any form, e.g. packed with the color, or relative to their own address.
+/// Nodes are only moved while they are detached, with both links NIL.
+///
+/// # Safety
+///
+/// The tree dereferences the pointers it reads from its nodes, so `left` and
+/// `right` must return the last pointers passed to `set_left` and
+/// `set_right`, and `key` must keep returning an equal key for as long as the
+/// node is in a tree.
+pub unsafe trait Node: Sized {
     type Key: Ord + Debug;
     type Ptr: NodePtr<Self>;
 
-    fn new(node: &Self) -> Self::Ptr;
-    fn free(&mut self);
-    fn left(&self) -> &Self::Ptr;
-    fn left_mut(&mut self) -> &mut Self::Ptr;
-    fn right(&self) -> &Self::Ptr;
-    fn right_mut(&mut self) -> &mut Self::Ptr;
+    fn left(&self) -> Self::Ptr;
+    fn set_left(&mut self, ptr: Self::Ptr);
+    fn right(&self) -> Self::Ptr;
+    fn set_right(&mut self, ptr: Self::Ptr);
     fn key(&self) -> &Self::Key;
-    fn update(&mut self, node: &Self);
 
     fn is_black(&self) -> bool;
     fn is_red(&self) -> bool {
@@ -26,54 +74,90 @@ pub trait Node: Sized {
 
     fn set_black(&mut self);
     fn set_red(&mut self);
+
+    /// Whether the node caches something about its subtree, such as its
+    /// size. The tree only calls `refresh` for such nodes.
+    const AUGMENTED: bool = false;
+
+    /// Recomputes what the node caches about its subtree from its children,
+    /// whose caches are up to date. The tree calls this bottom-up for every
+    /// node whose subtree changed.
+    ///
+    /// It must not panic: the tree is restructured around these calls, so a
+    /// panic aborts the process rather than unwind out of a broken tree.
+    fn refresh(&mut self, _left: Option<&Self>, _right: Option<&Self>) {}
+}
+
+/// A node whose key can be replaced while it is out of a tree, so that
+/// `RBTree::rekey` can move it to another key without reallocating it.
+pub trait SetKey: Node {
+    /// Replaces the key and returns the previous one.
+    fn set_key(&mut self, key: Self::Key) -> Self::Key;
 }
 
+/// A reference to a node. Pointers can only be dereferenced through the
+/// `NodeAlloc` which allocated them, so any reference to a node is bound to
+/// a borrow of the allocator, and thereby of the tree owning it.
 pub trait NodePtr<N: Node<Ptr = Self>>: Copy {
     const NIL: Self;
     fn is_nil(&self) -> bool;
-    fn node<'a>(&self) -> &'a N;
-    fn node_mut<'a>(&self) -> &'a mut N;
+}
 
-    fn is_black(&self) -> bool {
-        self.is_nil() || self.node().is_black()
+// Shorthands for dereferencing pointers within the tree algorithms. Every
+// pointer reachable from the root of a tree was handed out by the allocator
+// of that tree and is alive, and the references are never held across a
+// modification of the same node.
+#[allow(clippy::mut_from_ref)]
+trait PtrExt<N: Node<Ptr = Self>>: NodePtr<N> {
+    fn node<A: NodeAlloc<N>>(self, alloc: &A) -> &N {
+        unsafe { alloc.node(self) }
     }
 
-    fn is_red(&self) -> bool {
-        !self.is_black()
+    fn node_mut<A: NodeAlloc<N>>(self, alloc: &A) -> &mut N {
+        unsafe { alloc.node_mut(self) }
     }
-}
 
-struct Context<N: Node> {
-    parent: Option<(*mut Self, bool)>,
-    current: *mut N::Ptr
-}
+    fn is_black<A: NodeAlloc<N>>(self, alloc: &A) -> bool {
+        self.is_nil() || self.node(alloc).is_black()
+    }
+
+    fn is_red<A: NodeAlloc<N>>(self, alloc: &A) -> bool {
+        !self.is_black(alloc)
+    }
 
-impl<N: Node> Clone for Context<N> {
-    fn clone(&self) -> Self {
-        Self {
-            parent: self.parent,
-            current: self.current
+    // Whether both point to the same node, or are both NIL, going by the
+    // node addresses since pointers need not be comparable.
+    fn same_as<A: NodeAlloc<N>>(self, other: Self, alloc: &A) -> bool {
+        if self.is_nil() || other.is_nil() {
+            return self.is_nil() && other.is_nil();
         }
+        core::ptr::eq(self.node(alloc), other.node(alloc))
     }
 }
 
-impl<N: Node> Copy for Context<N> {
+impl<N: Node<Ptr = P>, P: NodePtr<N>> PtrExt<N> for P {}
+
+// Where the pointer to a node is stored: the root of the tree, or a child
+// link of another node. Slots are read and written through the tree each
+// time rather than held as references, so no reference into the tree is
+// alive while another part of it is modified.
+#[derive(Clone, Copy)]
+enum Slot<P> {
+    Root,
+    Left(P),
+    Right(P)
 }
 
-impl<N: Node> Context<N> {
-    fn ptr(&self) -> &mut N::Ptr {
-        unsafe { &mut *self.current }
-    }
-
-    fn parent_ptr(&self) -> &mut N::Ptr {
-        self.parent_ctx().ptr()
-    }
+struct Context<'a, N: Node> {
+    parent: Option<&'a Context<'a, N>>,
+    slot: Slot<N::Ptr>
+}
 
-    fn sibling_ptr(&self) -> &mut N::Ptr {
-        if self.is_left_child() {
-            self.parent_ptr().node_mut().right_mut()
-        } else {
-            self.parent_ptr().node_mut().left_mut()
+impl<'a, N: Node> Context<'a, N> {
+    fn root() -> Self {
+        Context {
+            parent: None,
+            slot: Slot::Root
         }
     }
 
@@ -82,51 +166,166 @@ impl<N: Node> Context<N> {
     }
 
     fn is_left_child(&self) -> bool {
-        self.parent.unwrap().1
+        matches!(self.slot, Slot::Left(_))
     }
 
-    fn parent_ctx(&self) -> &Self {
-        unsafe { &*self.parent.unwrap().0 }
+    fn parent(&self) -> &'a Self {
+        self.parent.unwrap()
     }
 
-    fn parent_ctx_mut(&self) -> &mut Self {
-        unsafe { &mut *self.parent.unwrap().0 }
+    fn sibling(&self) -> Slot<N::Ptr> {
+        match self.slot {
+            Slot::Left(p) => Slot::Right(p),
+            Slot::Right(p) => Slot::Left(p),
+            Slot::Root => unreachable!("the root has no sibling")
+        }
     }
 
-    fn left_ctx(&mut self) -> Self {
-        Context::<N> {
-            parent: Some((self as *mut Self, true)),
-            current: self.ptr().node_mut().left_mut()
+    fn left_ctx<'s, A: NodeAlloc<N>>(&'s self, tree: &RBTree<N, A>) -> Context<'s, N> {
+        Context {
+            parent: Some(self),
+            slot: Slot::Left(tree.load(self.slot))
         }
     }
 
-    fn right_ctx(&mut self) -> Self {
+    fn right_ctx<'s, A: NodeAlloc<N>>(&'s self, tree: &RBTree<N, A>) -> Context<'s, N> {
         Context {
-            parent: Some((self as *mut Self, false)),
-            current: self.ptr().node_mut().right_mut()
+            parent: Some(self),
+            slot: Slot::Right(tree.load(self.slot))
         }
     }
+}
 
-    fn has_left(&self) -> bool {
-        !self.ptr().node().left().is_nil()
+type AllocFn<'a, N, A> = dyn FnMut(&mut A, N) -> Result<<N as Node>::Ptr, AllocError<N>> + 'a;
+type RaisedFn<'a, N, A> = dyn FnMut(&A, <N as Node>::Ptr, <N as Node>::Ptr) + 'a;
+
+struct Insertion<'a, N: Node, A> {
+    alloc: &'a mut AllocFn<'a, N, A>,
+    // Compares a node on the way down with the node to insert.
+    probe: &'a mut dyn FnMut(&N, &N) -> Ordering,
+    // Whether the last repair rotated the parent of the current level, so
+    // that the route to the inserted node already starts from there.
+    rotated: bool,
+    // The nearest nodes passed on the way down which are before and after
+    // the inserted one.
+    lower: N::Ptr,
+    upper: N::Ptr
+}
+
+struct Deletion<'a, N: Node, A> {
+    // Compares a node on the way down with the node to remove.
+    probe: &'a mut dyn FnMut(&N) -> Ordering,
+    // Told of each rotation or swap moving a node up above another one.
+    raised: &'a mut RaisedFn<'a, N, A>,
+    unlinked: N::Ptr
+}
+
+pub struct RBTree<N: Node, A: NodeAlloc<N> = Heap> {
+    size: usize,
+    root: N::Ptr,
+    alloc: A,
+    // The route to the last inserted node, used as a hint for the next one.
+    last: Route,
+    // The last inserted node and its neighbours, while no node has left the
+    // tree since.
+    near: Option<Near<N::Ptr>>,
+    // The ancestors of the last inserted node from the root down to it, or
+    // empty if they are to be found again from `last`. Only valid along with
+    // `near`.
+    #[cfg(feature = "alloc")]
+    spine: alloc::vec::Vec<N::Ptr>
+}
+
+impl<N: Node, A: NodeAlloc<N> + Default> Default for RBTree<N, A> {
+    fn default() -> Self {
+        Self::with_alloc(A::default())
     }
+}
 
-    fn has_left_and_right(&self) -> bool {
-        let node = self.ptr().node();
-        !node.left().is_nil() && !node.right().is_nil()
+impl<N: Node> RBTree<N> where Heap: NodeAlloc<N> {
+    pub fn new() -> RBTree<N> {
+        Self::with_alloc(Heap)
     }
 }
 
-pub struct RBTree<N: Node> {
-    size: usize,
+// A tree owns all of its nodes, and by the contract of `NodeAlloc` they are
+// only reachable through its allocator, which only hands out `&mut N` for a
+// `&mut RBTree`. So the tree is as thread safe as a collection of `N` plus the
+// allocator, whatever `N::Ptr` is.
+unsafe impl<N: Node + Send, A: NodeAlloc<N> + Send> Send for RBTree<N, A> {}
+unsafe impl<N: Node + Sync, A: NodeAlloc<N> + Sync> Sync for RBTree<N, A> {}
+
+impl<N: Node, A: NodeAlloc<N>> Drop for RBTree<N, A> {
+    fn drop(&mut self) {
+        if !self.alloc.drop_tree(self.root, self.size) {
+            return;
+        }
+        let root = core::mem::replace(&mut self.root, N::Ptr::NIL);
+        drop(Detached { alloc: &mut self.alloc, root });
+    }
+}
+
+/// The nodes of a subtree which is no longer linked into a tree. They are
+/// released in key order by `next`, and the rest when this is dropped.
+pub(crate) struct Detached<'a, N: Node, A: NodeAlloc<N>> {
+    alloc: &'a mut A,
     root: N::Ptr
 }
 
-impl<N: Node> RBTree<N> {
-    pub fn new() -> RBTree<N> {
+impl<'a, N: Node, A: NodeAlloc<N>> Iterator for Detached<'a, N, A> {
+    type Item = N;
+
+    // Rotates the left child of the root up until there is none, then
+    // releases the root, so no memory is needed besides the nodes. The
+    // subtree is left unbalanced, which only serves to release it.
+    fn next(&mut self) -> Option<N> {
+        let alloc = &*self.alloc;
+        let mut ptr = self.root;
+        if ptr.is_nil() {
+            return None;
+        }
+        let mut left = ptr.node(alloc).left();
+        while !left.is_nil() {
+            ptr.node_mut(alloc).set_left(left.node(alloc).right());
+            left.node_mut(alloc).set_right(ptr);
+            ptr = left;
+            left = ptr.node(alloc).left();
+        }
+        self.root = ptr.node(alloc).right();
+        Some(RBTree::release(self.alloc, ptr))
+    }
+}
+
+impl<'a, N: Node, A: NodeAlloc<N>> Drop for Detached<'a, N, A> {
+    fn drop(&mut self) {
+        // Keeps releasing the nodes if dropping one of them panics.
+        struct Guard<'r, 'a, N: Node, A: NodeAlloc<N>>(&'r mut Detached<'a, N, A>);
+
+        impl<'r, 'a, N: Node, A: NodeAlloc<N>> Drop for Guard<'r, 'a, N, A> {
+            fn drop(&mut self) {
+                self.0.for_each(drop);
+            }
+        }
+
+        while let Some(node) = self.next() {
+            let guard = Guard(self);
+            drop(node);
+            core::mem::forget(guard);
+        }
+    }
+}
+
+impl<N: Node, A: NodeAlloc<N>> RBTree<N, A> {
+    /// Creates an empty tree whose nodes are allocated by `alloc`.
+    pub const fn with_alloc(alloc: A) -> RBTree<N, A> {
         RBTree {
             size: 0,
-            root: N::Ptr::NIL
+            root: N::Ptr::NIL,
+            alloc,
+            last: Route::NONE,
+            near: None,
+            #[cfg(feature = "alloc")]
+            spine: alloc::vec::Vec::new()
         }
     }
 
@@ -134,20 +333,57 @@ impl<N: Node> RBTree<N> {
         self.size
     }
 
-    fn root_context(&mut self) -> Context<N> {
-        Context {
-            parent: None,
-            current: &mut self.root as *mut N::Ptr
+    pub fn alloc(&self) -> &A {
+        &self.alloc
+    }
+
+    fn load(&self, slot: Slot<N::Ptr>) -> N::Ptr {
+        match slot {
+            Slot::Root => self.root,
+            Slot::Left(p) => p.node(&self.alloc).left(),
+            Slot::Right(p) => p.node(&self.alloc).right()
+        }
+    }
+
+    fn store(&mut self, slot: Slot<N::Ptr>, ptr: N::Ptr) {
+        match slot {
+            Slot::Root => self.root = ptr,
+            Slot::Left(p) => p.node_mut(&self.alloc).set_left(ptr),
+            Slot::Right(p) => p.node_mut(&self.alloc).set_right(ptr)
         }
     }
 
+    /// Returns the node with the given key. The node is borrowed from the
+    /// tree, so it can't be kept across a removal, which may free it:
+    ///
+    /// ```compile_fail
+    /// use red_black::{KeyValue, RBTree};
+    ///
+    /// let mut tree = RBTree::new();
+    /// tree.insert_owned(KeyValue::new(1, "one"));
+    /// let node = tree.search(&1).unwrap();
+    /// tree.remove(&1);
+    /// assert_eq!("one", *node.value());
+    /// ```
+    ///
+    /// or across an insertion, which may replace it:
+    ///
+    /// ```compile_fail
+    /// use red_black::{KeyValue, RBTree};
+    ///
+    /// let mut tree = RBTree::new();
+    /// tree.insert_owned(KeyValue::new(1, "one"));
+    /// let node = tree.search(&1).unwrap();
+    /// tree.insert_owned(KeyValue::new(1, "uno"));
+    /// assert_eq!("one", *node.value());
+    /// ```
     pub fn search(&self, key: &N::Key) -> Option<&N> {
-        let mut ptr = &self.root;
+        let mut ptr = self.root;
         loop {
             if ptr.is_nil() {
                 return None;
             }
-            let node = ptr.node();
+            let node = ptr.node(&self.alloc);
             match node.key().cmp(key) {
                 Ordering::Equal => { return Some(node) }
                 Ordering::Less => { ptr = node.right() }
@@ -156,206 +392,551 @@ impl<N: Node> RBTree<N> {
         }
     }
 
-    pub fn insert(&mut self, node: &N) -> bool {
-        let inserted = Self::do_insert(self.root_context(), node);
-        if inserted {
+    pub fn insert(&mut self, node: &N) -> bool where N: Clone {
+        self.insert_owned(node.clone()).is_none()
+    }
+
+    /// Moves a node into the tree. If a node with the same key is already in
+    /// the tree, it is replaced and returned.
+    ///
+    /// The last inserted node and its neighbours are kept, and a node which
+    /// belongs right between them, as one or two comparisons with them tell,
+    /// is linked next to the last one without searching. With the `alloc`
+    /// feature the ancestors of the last node are kept as well, and the tree
+    /// is rebalanced up from there, so appending keys in order takes a
+    /// single comparison and amortized O(1) time per node. Without it, the
+    /// route to the last node is followed from the root, which skips the
+    /// comparisons but not the O(log n) links.
+    pub fn insert_owned(&mut self, node: N) -> Option<N> {
+        match self.insert_next_to_last(node, &mut |alloc, node| Ok(alloc.alloc(node))) {
+            Ok(replaced) => replaced,
+            Err(_) => unreachable!("infallible allocation failed")
+        }
+    }
+
+    /// Like `insert_owned`, but hands the node back, leaving the tree
+    /// unchanged, if the allocator has no memory for it.
+    pub fn try_insert(&mut self, node: N) -> Result<Option<N>, AllocError<N>> {
+        self.insert_next_to_last(node, &mut |alloc, node| alloc.try_alloc(node))
+    }
+
+    // Inserts the node next to the last inserted one if it belongs there, or
+    // else descends from the root comparing keys.
+    fn insert_next_to_last(&mut self, node: N, alloc: &mut AllocFn<'_, N, A>) -> Result<Option<N>, AllocError<N>> {
+        let side = self.side_of_last(node.key());
+        #[cfg(feature = "alloc")]
+        if let Some(side) = side {
+            if self.follow_spine() {
+                return self.insert_at_last(node, side, alloc);
+            }
+        }
+        let route = side.and_then(|side| self.route_at_last(side));
+        self.insert_by(node, route, alloc)
+    }
+
+    pub(crate) fn insert_near(&mut self, node: N, route: Option<Route>) -> Option<N> {
+        match self.insert_by(node, route, &mut |alloc, node| Ok(alloc.alloc(node))) {
+            Ok(replaced) => replaced,
+            Err(_) => unreachable!("infallible allocation failed")
+        }
+    }
+
+    // Inserts the node at the end of `route` if there is one, or else
+    // descends from the root comparing keys.
+    fn insert_by(&mut self, node: N, route: Option<Route>, alloc: &mut AllocFn<'_, N, A>) -> Result<Option<N>, AllocError<N>> {
+        match route {
+            Some(route) => self.insert_with(node, &mut route.probe(), alloc),
+            None => self.insert_with(node, &mut |current, node| current.key().cmp(node.key()), alloc)
+        }
+    }
+
+    /// Inserts the node where `probe` leads, comparing the nodes on the way
+    /// down with the new one.
+    pub(crate) fn insert_with(&mut self, node: N, probe: &mut dyn FnMut(&N, &N) -> Ordering, alloc: &mut AllocFn<'_, N, A>) -> Result<Option<N>, AllocError<N>> {
+        let op = &mut Insertion { alloc, probe, rotated: false, lower: N::Ptr::NIL, upper: N::Ptr::NIL };
+        let replaced = self.do_insert(&Context::root(), node, op)?;
+        if replaced.is_none() {
             self.size += 1;
         }
-        return inserted;
+        #[cfg(feature = "alloc")]
+        self.spine.clear();
+        Ok(replaced)
     }
 
-    fn do_insert(mut ctx: Context<N>, node: &N) -> bool {
-        let current_ptr = ctx.ptr();
+    // The node is allocated only once its place is found, and nothing is
+    // modified on the way down, so a failed allocation leaves the tree as is.
+    fn do_insert(&mut self, ctx: &Context<N>, mut node: N, op: &mut Insertion<N, A>) -> Result<Option<N>, AllocError<N>> {
+        let current_ptr = self.load(ctx.slot);
         if current_ptr.is_nil() {
-            *current_ptr = N::new(node);
+            node.set_left(N::Ptr::NIL);
+            node.set_right(N::Ptr::NIL);
+            node.set_red();
+            let ptr = (op.alloc)(&mut self.alloc, node)?;
+            Self::refresh(&self.alloc, ptr);
+            self.store(ctx.slot, ptr);
             if ctx.is_root() {
-                current_ptr.node_mut().set_black();
+                ptr.node_mut(&self.alloc).set_black();
             }
-            return true;
+            self.last = Route::EMPTY;
+            self.near = Some(Near { node: ptr, prev: op.lower, next: op.upper });
+            return Ok(None);
         }
-        let current_node = current_ptr.node_mut();
-        let next_ctx = match current_node.key().cmp(node.key()) {
+        let next_ctx = match (op.probe)(current_ptr.node(&self.alloc), &node) {
             Ordering::Equal => {
-                current_node.update(node);
-                return false;
+                self.last = Route::EMPTY;
+                let replaced = Self::replace(current_ptr.node_mut(&self.alloc), node);
+                Self::refresh(&self.alloc, current_ptr);
+                let prev = self.closest(current_ptr, false);
+                let next = self.closest(current_ptr, true);
+                self.near = Some(Near {
+                    node: current_ptr,
+                    prev: if prev.is_nil() { op.lower } else { prev },
+                    next: if next.is_nil() { op.upper } else { next }
+                });
+                return Ok(Some(replaced));
+            }
+            Ordering::Less => {
+                op.lower = current_ptr;
+                ctx.right_ctx(self)
+            }
+            Ordering::Greater => {
+                op.upper = current_ptr;
+                ctx.left_ctx(self)
             }
-            Ordering::Less => { ctx.right_ctx() }
-            Ordering::Greater => { ctx.left_ctx() }
         };
-        let inserted = Self::do_insert(next_ctx, node);
-        if inserted && ctx.ptr().node().is_red() {
+        let replaced = self.do_insert(&next_ctx, node, op)?;
+        // Keeps the route to the inserted node valid through the rotations.
+        if op.rotated {
+            op.rotated = false;
+        } else {
+            self.last.push_front(!next_ctx.is_left_child());
+        }
+        Self::refresh(&self.alloc, self.load(ctx.slot));
+        if replaced.is_none() && self.load(ctx.slot).is_red(&self.alloc) {
             if ctx.is_root() {
-                ctx.ptr().node_mut().set_black();
-            } else if next_ctx.ptr().node().is_red() {
-                Self::insert_repair(ctx, next_ctx.is_left_child())
+                self.load(ctx.slot).node_mut(&self.alloc).set_black();
+            } else if self.load(next_ctx.slot).is_red(&self.alloc) && self.insert_repair(ctx, next_ctx.is_left_child()) {
+                if ctx.is_left_child() != next_ctx.is_left_child() {
+                    self.last.rotate_inner();
+                }
+                op.rotated = true;
             }
         }
-        inserted
+        Ok(replaced)
     }
 
-    fn insert_repair(ctx: Context<N>, inserted_at_left: bool) {
-        if !ctx.sibling_ptr().is_nil() {
-            let sibling_node = ctx.sibling_ptr().node_mut();
-            if sibling_node.is_red() {
-                ctx.ptr().node_mut().set_black();
-                sibling_node.set_black();
-                ctx.parent_ptr().node_mut().set_red();
-                return;
-            }
+    // Moves `node` into the place of `existing` in the tree, and returns the
+    // content of `existing`.
+    fn replace(existing: &mut N, node: N) -> N {
+        let (left, right, black) = (existing.left(), existing.right(), existing.is_black());
+        let replaced = core::mem::replace(existing, Self::detached(node));
+        existing.set_left(left);
+        existing.set_right(right);
+        if black { existing.set_black() } else { existing.set_red() }
+        Self::detached(replaced)
+    }
+
+    fn detached(mut node: N) -> N {
+        node.set_left(N::Ptr::NIL);
+        node.set_right(N::Ptr::NIL);
+        node
+    }
+
+    /// Releases a node which is no longer linked into the tree and returns
+    /// its content.
+    pub(crate) fn release(alloc: &mut A, ptr: N::Ptr) -> N {
+        Self::detached(unsafe { alloc.free(ptr) })
+    }
+
+    // Returns whether the parent was rotated, rather than only recoloured.
+    fn insert_repair(&mut self, ctx: &Context<N>, inserted_at_left: bool) -> bool {
+        let parent_slot = ctx.parent().slot;
+        let sibling = self.load(ctx.sibling());
+        if sibling.is_red(&self.alloc) {
+            self.load(ctx.slot).node_mut(&self.alloc).set_black();
+            sibling.node_mut(&self.alloc).set_black();
+            self.load(parent_slot).node_mut(&self.alloc).set_red();
+            return false;
         }
         if ctx.is_left_child() {
             if !inserted_at_left {
-                Self::rotate_left(ctx.ptr());
+                self.rotate_left(ctx.slot);
             }
-            ctx.ptr().node_mut().set_black();
-            ctx.parent_ptr().node_mut().set_red();
-            Self::rotate_right(ctx.parent_ptr());
+            self.load(ctx.slot).node_mut(&self.alloc).set_black();
+            self.load(parent_slot).node_mut(&self.alloc).set_red();
+            self.rotate_right(parent_slot);
         } else {
             if inserted_at_left {
-                Self::rotate_right(ctx.ptr());
+                self.rotate_right(ctx.slot);
             }
-            ctx.ptr().node_mut().set_black();
-            ctx.parent_ptr().node_mut().set_red();
-            Self::rotate_left(ctx.parent_ptr());
+            self.load(ctx.slot).node_mut(&self.alloc).set_black();
+            self.load(parent_slot).node_mut(&self.alloc).set_red();
+            self.rotate_left(parent_slot);
         }
+        true
     }
 
     pub fn delete(&mut self, key: &N::Key) -> bool {
-        let mut deleted_node: N::Ptr = N::Ptr::NIL;
-        Self::do_delete(self.root_context(), key, &mut deleted_node);
-        let deleted = !deleted_node.is_nil();
-        if deleted {
-            deleted_node.node_mut().free();
+        self.remove(key).is_some()
+    }
+
+    /// Removes the node with the given key from the tree and returns it.
+    pub fn remove(&mut self, key: &N::Key) -> Option<N> {
+        let removed = self.unlink_by(&mut |node| node.key().cmp(key));
+        if removed.is_nil() {
+            None
+        } else {
+            Some(Self::release(&mut self.alloc, removed))
+        }
+    }
+
+    /// Moves the node with the key `old` to `key`, keeping its allocation
+    /// and value, and returns the old key. Hands `key` back, leaving the tree
+    /// unchanged, if there is no node with `old` or another node has `key`.
+    ///
+    /// Both keys are looked up before the node is unlinked, and the nodes
+    /// passed on the way to the new place tell the way back down to it, so
+    /// no comparison runs while the node is out of the tree.
+    pub fn rekey(&mut self, old: &N::Key, key: N::Key) -> Result<N::Key, N::Key> where N: SetKey {
+        let alloc = &self.alloc;
+        let mut at = Route::EMPTY;
+        let mut ptr = self.root;
+        loop {
+            if ptr.is_nil() {
+                return Err(key);
+            }
+            let node = ptr.node(alloc);
+            let right = match node.key().cmp(old) {
+                Ordering::Equal => break,
+                Ordering::Less => true,
+                Ordering::Greater => false
+            };
+            at.push_back(right);
+            ptr = if right { node.right() } else { node.left() };
+        }
+        let target = ptr.node(alloc);
+        // The nodes on the way down to where `key` belongs, and the closest
+        // of them before and after that place.
+        let mut sides = Sides::new();
+        let (mut lower, mut upper) = (N::Ptr::NIL, N::Ptr::NIL);
+        let mut current = self.root;
+        while !current.is_nil() {
+            let node = current.node(alloc);
+            let right = match node.key().cmp(&key) {
+                Ordering::Equal if core::ptr::eq(node, target) => {
+                    upper = current;
+                    break;
+                }
+                Ordering::Equal => return Err(key),
+                Ordering::Less => true,
+                Ordering::Greater => false
+            };
+            sides.push(current, right);
+            if right { lower = current } else { upper = current }
+            current = if right { node.right() } else { node.left() };
+        }
+        // A key next to the node's own keeps its place in the order, so it
+        // is replaced where the node is.
+        let here = |near: N::Ptr| near.same_as(ptr, alloc);
+        if here(lower) || here(upper) {
+            let old = ptr.node_mut(alloc).set_key(key);
+            Self::refresh(alloc, ptr);
+            for (passed, _) in sides.rev() {
+                Self::refresh(alloc, passed);
+            }
+            return Ok(old);
+        }
+        let mut directions = at.directions();
+        let unlinked = self.unlink_with(&mut |_| directions(), &mut |alloc, up, down| sides.raised(alloc, up, down));
+        debug_assert!(core::ptr::eq(unlinked.node(&self.alloc), ptr.node(&self.alloc)));
+        let node = ptr.node_mut(&self.alloc);
+        node.set_left(N::Ptr::NIL);
+        node.set_right(N::Ptr::NIL);
+        let old = node.set_key(key);
+        let mut route = Route::EMPTY;
+        let mut current = self.root;
+        while !current.is_nil() {
+            let right = sides.side(&self.alloc, current).expect("a node above the new place is not known");
+            route.push_back(right);
+            let node = current.node(&self.alloc);
+            current = if right { node.right() } else { node.left() };
+        }
+        // The node is moved out and back into its own memory once its place
+        // is reached.
+        let node = unsafe { core::ptr::read(ptr.node(&self.alloc)) };
+        let relinked = self.insert_with(node, &mut route.probe(), &mut |alloc, node| {
+            unsafe { core::ptr::write(alloc.node_mut(ptr), node) };
+            Ok(ptr)
+        });
+        debug_assert!(matches!(relinked, Ok(None)));
+        Ok(old)
+    }
+
+    /// Detaches the node located by `probe` from the tree without releasing
+    /// it, or returns NIL if there is no such node.
+    pub(crate) fn unlink_by(&mut self, probe: &mut dyn FnMut(&N) -> Ordering) -> N::Ptr {
+        self.unlink_with(probe, &mut |_, _, _| {})
+    }
+
+    // Like `unlink_by`, and tells `raised` of each node which the repairs
+    // move up above another one, with that node.
+    fn unlink_with(&mut self, probe: &mut dyn FnMut(&N) -> Ordering, raised: &mut RaisedFn<'_, N, A>) -> N::Ptr {
+        let op = &mut Deletion { probe, raised, unlinked: N::Ptr::NIL };
+        self.do_delete(&Context::root(), op);
+        if !op.unlinked.is_nil() {
             self.size -= 1;
+            self.near = None;
         }
-        return deleted;
+        op.unlinked
     }
 
-    fn do_delete(mut ctx: Context<N>, key: &N::Key, deleted_node: &mut N::Ptr) -> bool {
-        let current_ptr = ctx.ptr();
+    fn do_delete(&mut self, ctx: &Context<N>, op: &mut Deletion<N, A>) -> bool {
+        let current_ptr = self.load(ctx.slot);
         if current_ptr.is_nil() {
             return false;
         }
-        let current_node = current_ptr.node_mut();
-        let next_ctx = match current_node.key().cmp(key) {
+        let next_ctx = match (op.probe)(current_ptr.node(&self.alloc)) {
             Ordering::Equal => {
-                if ctx.has_left_and_right() {
-                    (*deleted_node).clone_from(ctx.ptr());
-                    let need_repair = Self::delete_left_most(ctx.right_ctx(), deleted_node);
-                    return need_repair && Self::delete_repair(ctx);
+                let node = current_ptr.node(&self.alloc);
+                if !node.left().is_nil() && !node.right().is_nil() {
+                    self.swap_with_successor(ctx.slot, op);
+                    let need_repair = self.delete_left_most(&ctx.right_ctx(self), op);
+                    Self::refresh(&self.alloc, self.load(ctx.slot));
+                    return need_repair && self.delete_repair(ctx, op);
                 } else {
-                    return Self::delete_node(ctx, deleted_node);
+                    return self.delete_node(ctx, op);
                 };
             }
-            Ordering::Less => { ctx.right_ctx() }
-            Ordering::Greater => { ctx.left_ctx() }
+            Ordering::Less => { ctx.right_ctx(self) }
+            Ordering::Greater => { ctx.left_ctx(self) }
         };
-        Self::do_delete(next_ctx, key, deleted_node) && Self::delete_repair(ctx)
+        // The subtree is refreshed before the repair, which may rotate it
+        // below a sibling.
+        let need_repair = self.do_delete(&next_ctx, op);
+        Self::refresh(&self.alloc, self.load(ctx.slot));
+        need_repair && self.delete_repair(ctx, op)
+    }
+
+    // Swaps the positions of a node having two children and its in-order
+    // successor, so that the node ends up as the left most node of its right
+    // subtree. The nodes are relinked rather than having their contents moved,
+    // so the deleted node can be handed out intact.
+    fn swap_with_successor(&mut self, slot: Slot<N::Ptr>, op: &mut Deletion<N, A>) {
+        let alloc = &self.alloc;
+        let x = self.load(slot);
+        let x_left = x.node(alloc).left();
+        let x_right = x.node(alloc).right();
+        let mut parent = x;
+        let mut s = x_right;
+        let mut direct = true;
+        while !s.node(alloc).left().is_nil() {
+            parent = s;
+            s = s.node(alloc).left();
+            direct = false;
+        }
+        let s_right = s.node(alloc).right();
+        if direct {
+            s.node_mut(alloc).set_right(x);
+        } else {
+            s.node_mut(alloc).set_right(x_right);
+            parent.node_mut(alloc).set_left(x);
+        }
+        s.node_mut(alloc).set_left(x_left);
+        x.node_mut(alloc).set_left(N::Ptr::NIL);
+        x.node_mut(alloc).set_right(s_right);
+
+        let x_black = x.is_black(alloc);
+        let s_black = s.is_black(alloc);
+        if s_black { x.node_mut(alloc).set_black() } else { x.node_mut(alloc).set_red() }
+        if x_black { s.node_mut(alloc).set_black() } else { s.node_mut(alloc).set_red() }
+        self.store(slot, s);
+        (op.raised)(&self.alloc, s, x);
     }
 
-    fn delete_left_most<'a>(mut ctx: Context<N>, deleted_node: &mut N::Ptr) -> bool {
-        if ctx.has_left() {
-            Self::delete_left_most(ctx.left_ctx(), deleted_node) && Self::delete_repair(ctx)
+    fn delete_left_most(&mut self, ctx: &Context<N>, op: &mut Deletion<N, A>) -> bool {
+        if !self.load(ctx.slot).node(&self.alloc).left().is_nil() {
+            let need_repair = self.delete_left_most(&ctx.left_ctx(self), op);
+            Self::refresh(&self.alloc, self.load(ctx.slot));
+            need_repair && self.delete_repair(ctx, op)
         } else {
-            (*deleted_node).node_mut().update(ctx.ptr().node());
-            Self::delete_node(ctx, deleted_node)
+            self.delete_node(ctx, op)
         }
     }
 
-    fn delete_node<'a>(ctx: Context<N>, deleted_node: &mut N::Ptr) -> bool {
-        *deleted_node = *ctx.ptr();
-        let n = deleted_node.node_mut();
+    fn delete_node(&mut self, ctx: &Context<N>, op: &mut Deletion<N, A>) -> bool {
+        op.unlinked = self.load(ctx.slot);
+        let n = op.unlinked.node(&self.alloc);
         let c = if !n.left().is_nil() { n.left() } else { n.right() };
-        ctx.ptr().clone_from(c);
+        let n_red = n.is_red();
+        self.store(ctx.slot, c);
 
-        if n.is_red() {
+        if n_red {
             return false;
         }
 
-        if c.is_red() {
-            c.node_mut().set_black();
+        if c.is_red(&self.alloc) {
+            c.node_mut(&self.alloc).set_black();
             return false;
         }
 
-        return Self::delete_repair(ctx);
+        self.delete_repair(ctx, op)
     }
 
-    fn delete_repair(mut ctx: Context<N>) -> bool {
+    fn delete_repair(&mut self, ctx: &Context<N>, op: &mut Deletion<N, A>) -> bool {
         if ctx.is_root() {
             return false;
         }
 
-        let p = ctx.parent_ptr();
-        let s = ctx.sibling_ptr();
-        if s.is_red() {
-            p.node_mut().set_red();
-            s.node_mut().set_black();
-            if ctx.is_left_child() {
-                Self::rotate_left(p);
-                ctx = ctx.parent_ctx_mut().left_ctx().left_ctx();
+        let p_slot = ctx.parent().slot;
+        let s_slot = ctx.sibling();
+        let p = self.load(p_slot);
+        let s = self.load(s_slot);
+        if s.is_red(&self.alloc) {
+            p.node_mut(&self.alloc).set_red();
+            s.node_mut(&self.alloc).set_black();
+            // The parent moves down below the sibling, and the current node
+            // stays its child, so continue from the new parent context.
+            let parent = if ctx.is_left_child() {
+                self.rotate_left(p_slot);
+                ctx.parent().left_ctx(self)
             } else {
-                Self::rotate_right(p);
-                ctx = ctx.parent_ctx_mut().right_ctx().right_ctx();
-            }
+                self.rotate_right(p_slot);
+                ctx.parent().right_ctx(self)
+            };
+            (op.raised)(&self.alloc, s, p);
+            return self.delete_repair(&Context { parent: Some(&parent), slot: ctx.slot }, op);
         }
 
-        let p = ctx.parent_ptr();
-        let s = ctx.sibling_ptr();
-        if s.is_black() && s.node().left().is_black() && s.node().right().is_black() {
-            if p.is_black() {
-                s.node_mut().set_red();
+        let alloc = &self.alloc;
+        let sl = s.node(alloc).left();
+        let sr = s.node(alloc).right();
+        if sl.is_black(alloc) && sr.is_black(alloc) {
+            if p.is_black(alloc) {
+                s.node_mut(alloc).set_red();
                 return true;
             }
-            s.node_mut().set_red();
-            p.node_mut().set_black();
+            s.node_mut(alloc).set_red();
+            p.node_mut(alloc).set_black();
             return false;
         }
 
         if ctx.is_left_child() {
-            if s.node().right().is_black() {
-                s.node_mut().set_red();
-                s.node().left().node_mut().set_black();
-                Self::rotate_right(s);
+            if sr.is_black(alloc) {
+                s.node_mut(alloc).set_red();
+                sl.node_mut(alloc).set_black();
+                self.rotate_right(s_slot);
+                (op.raised)(&self.alloc, sl, s);
             }
         } else {
-            if s.node().left().is_black() {
-                s.node_mut().set_red();
-                s.node().right().node_mut().set_black();
-                Self::rotate_left(s);
+            if sl.is_black(alloc) {
+                s.node_mut(alloc).set_red();
+                sr.node_mut(alloc).set_black();
+                self.rotate_left(s_slot);
+                (op.raised)(&self.alloc, sr, s);
             }
         }
 
-        if p.node().is_red() {
-            s.node_mut().set_red();
-            p.node_mut().set_black();
+        let alloc = &self.alloc;
+        let s = self.load(s_slot);
+        if p.is_red(alloc) {
+            s.node_mut(alloc).set_red();
+            p.node_mut(alloc).set_black();
         }
         if ctx.is_left_child() {
-            s.node_mut().right_mut().node_mut().set_black();
-            Self::rotate_left(p);
+            s.node(alloc).right().node_mut(alloc).set_black();
+            self.rotate_left(p_slot);
         } else {
-            s.node_mut().left_mut().node_mut().set_black();
-            Self::rotate_right(p);
+            s.node(alloc).left().node_mut(alloc).set_black();
+            self.rotate_right(p_slot);
         }
-        return false;
+        (op.raised)(&self.alloc, s, p);
+        false
+    }
+
+    fn rotate_left(&mut self, slot: Slot<N::Ptr>) {
+        let top = Self::rotated_left(&self.alloc, self.load(slot));
+        self.store(slot, top);
+    }
+
+    fn rotate_right(&mut self, slot: Slot<N::Ptr>) {
+        let top = Self::rotated_right(&self.alloc, self.load(slot));
+        self.store(slot, top);
     }
 
-    fn rotate_left(ptr: &mut N::Ptr) {
-        let me = ptr.clone();
-        let r = me.node().right().clone();
-        let rl = r.node().left().clone();
-        *ptr = r;
-        *me.node_mut().right_mut() = rl;
-        *r.node_mut().left_mut() = me;
+    // Rotates the subtree of `me` and returns its new root.
+    pub(crate) fn rotated_left(alloc: &A, me: N::Ptr) -> N::Ptr {
+        let r = me.node(alloc).right();
+        let rl = r.node(alloc).left();
+        me.node_mut(alloc).set_right(rl);
+        r.node_mut(alloc).set_left(me);
+        Self::refresh(alloc, me);
+        Self::refresh(alloc, r);
+        r
     }
 
-    fn rotate_right(ptr: &mut N::Ptr) {
-        let me = ptr.clone();
-        let l = me.node().left().clone();
-        let lr = l.node().right().clone();
-        *ptr = l;
-        *me.node_mut().left_mut() = lr;
-        *l.node_mut().right_mut() = me;
+    pub(crate) fn rotated_right(alloc: &A, me: N::Ptr) -> N::Ptr {
+        let l = me.node(alloc).left();
+        let lr = l.node(alloc).right();
+        me.node_mut(alloc).set_left(lr);
+        l.node_mut(alloc).set_right(me);
+        Self::refresh(alloc, me);
+        Self::refresh(alloc, l);
+        l
+    }
+
+    /// Recomputes the cache of an augmented node from its children.
+    pub(crate) fn refresh(alloc: &A, ptr: N::Ptr) {
+        if !N::AUGMENTED || ptr.is_nil() {
+            return;
+        }
+        let node = ptr.node(alloc);
+        let (left, right) = (node.left(), node.right());
+        let left = if left.is_nil() { None } else { Some(left.node(alloc)) };
+        let right = if right.is_nil() { None } else { Some(right.node(alloc)) };
+        // Nodes are refreshed in the middle of rotations and repairs, which
+        // can't be unwound, so a panic there aborts.
+        let guard = AbortOnUnwind;
+        ptr.node_mut(alloc).refresh(left, right);
+        core::mem::forget(guard);
+    }
+}
+
+// Panics if dropped, which aborts when it is dropped by unwinding.
+struct AbortOnUnwind;
+
+impl Drop for AbortOnUnwind {
+    fn drop(&mut self) {
+        panic!("Node::refresh panicked, which would leave the tree inconsistent");
     }
 }
 
-pub type KeyValue<K, V> = kv::KeyValue<K, V>;
\ No newline at end of file
+pub use aggregate::{AggregateNode, AggregateNodePtr, Count, Counted, Max, Min, Monoid, Sum};
+pub use allocator::{AllocError, Counting, Heap, NodeAlloc, RawNodePtr};
+#[cfg(feature = "alloc")]
+pub use allocator::Arena;
+pub type KeyValue<K, V> = kv::KeyValue<K, V>;
+#[cfg(feature = "alloc")]
+pub use cursor::{Cursor, CursorMut, UnorderedKeyError};
+#[cfg(feature = "alloc")]
+pub use handle::{Handle, HandleEntry, HandleMap};
+pub use hint::Hint;
+pub use inline::{Inline, SlotIndex, StaticNode, StaticRBTree};
+#[cfg(feature = "std")]
+pub use mapped::{Mapped, MappedNode, MappedNodePtr, OffsetNodePtr, Plain};
+pub use median::{MedianNode, RunningMedian};
+#[cfg(feature = "alloc")]
+pub use multimap::{GetAll, MultiMapEntry, RBMultiMap};
+#[cfg(feature = "alloc")]
+pub use nearest::{Distance, Nearest};
+#[cfg(feature = "alloc")]
+pub use prefix::{HasPrefix, PrefixIter};
+#[cfg(feature = "alloc")]
+pub use range_map::{RangeEntry, RangeMap, Ranges};
+#[cfg(feature = "std")]
+pub use region::{Region, RelNode, RelNodePtr};
+#[cfg(feature = "alloc")]
+pub use retain::ExtractIf;
+#[cfg(feature = "alloc")]
+pub use sequence::{SeqIter, SeqNode, SeqNodePtr, Sequence};
+#[cfg(feature = "std")]
+pub use snapshot::Codec;
\ No newline at end of file
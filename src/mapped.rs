@@ -0,0 +1,409 @@
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Error, ErrorKind};
+use std::marker::PhantomData;
+use std::mem::{align_of, offset_of, size_of};
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use memmap2::MmapMut;
+
+use crate::{AllocError, Node, NodeAlloc, NodePtr, RBTree, SetKey};
+
+/// A type which can be written to a file and read back by another process:
+/// it owns and borrows nothing, and every bit pattern is a valid value.
+///
+/// Tuples are left out, as their layout may differ between builds. A struct
+/// of plain fields is plain if it is `#[repr(C)]`.
+///
+/// # Safety
+///
+/// Any bytes of the size of the type must make a valid value of it, and its
+/// layout must be fixed by its definition, as with `#[repr(C)]`.
+pub unsafe trait Plain: Copy + 'static {}
+
+macro_rules! plain {
+    ($($t:ty),*) => { $(unsafe impl Plain for $t {})* }
+}
+
+plain!((), u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Plain, const L: usize> Plain for [T; L] {}
+
+/// A `NodePtr` which is an offset into the file of a `Mapped` allocator, so
+/// it stays valid wherever the file is mapped.
+///
+/// # Safety
+///
+/// `offset` must return the offset passed to `from_offset`, and NIL must be
+/// the pointer with the offset 0.
+pub unsafe trait OffsetNodePtr<N: Node<Ptr = Self>>: NodePtr<N> {
+    fn from_offset(offset: u64) -> Self;
+    fn offset(self) -> u64;
+}
+
+const MAGIC: [u8; 8] = *b"RBTREE\0\0";
+const VERSION: u32 = 1;
+const HEADER: usize = 64;
+const MIN_SLOTS: usize = 1024;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Header {
+    magic: [u8; 8],
+    version: u32,
+    node_size: u32,
+    node_align: u32,
+    // Set while the nodes in the file may be newer than the root recorded
+    // here, from the first change after the file is opened or synced.
+    dirty: u32,
+    root: u64,
+    size: u64,
+    // The number of node slots ever handed out, and the offset of the first
+    // released one. Released slots are chained through their first 8 bytes.
+    used: u64,
+    free: u64
+}
+
+/// Allocates nodes in a memory-mapped file, so that a tree can be opened
+/// again by a later process without re-inserting its nodes.
+///
+/// The nodes are written to the file through the mapping as the tree is
+/// modified, while the root of the tree is only recorded by
+/// `RBTree::flush` and when the tree is dropped. The file is marked as
+/// modified on disk before the first node is, so a file left behind by a
+/// process which crashed in between is refused by `RBTree::open`.
+pub struct Mapped<N> {
+    file: File,
+    map: MmapMut,
+    base: *mut u8,
+    header: Header,
+    // Whether the file is marked as modified, and why marking it failed.
+    // Nodes may be modified through a shared reference, so the first one to
+    // be marks the file while holding the lock.
+    dirty: AtomicBool,
+    failed: Mutex<Option<Error>>,
+    node: PhantomData<N>
+}
+
+// `base` points into `map`, which the allocator owns, and `File` and
+// `MmapMut` cross threads themselves. Other writers to the file are ruled
+// out by the contract of `RBTree::open`, so only the nodes are left, and
+// the dirty flag, which is atomic and set under a lock.
+unsafe impl<N: Send> Send for Mapped<N> {}
+unsafe impl<N: Sync> Sync for Mapped<N> {}
+
+impl<N: Plain> Mapped<N> {
+    const NODE_SIZE: usize = size_of::<N>();
+
+    unsafe fn open(path: &Path) -> io::Result<Mapped<N>> {
+        if Self::NODE_SIZE < size_of::<u64>() || align_of::<N>() > HEADER {
+            return Err(Error::new(ErrorKind::InvalidInput, "node type can't be mapped"));
+        }
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let created = file.metadata()?.len() == 0;
+        let header = if created {
+            file.set_len((HEADER + MIN_SLOTS * Self::NODE_SIZE) as u64)?;
+            Header {
+                magic: MAGIC,
+                version: VERSION,
+                node_size: Self::NODE_SIZE as u32,
+                node_align: align_of::<N>() as u32,
+                dirty: 0,
+                root: 0,
+                size: 0,
+                used: 0,
+                free: 0
+            }
+        } else {
+            let mut header = [0; HEADER];
+            io::Read::read_exact(&mut &file, &mut header)?;
+            ptr::read_unaligned(header.as_ptr() as *const Header)
+        };
+        let mut map = MmapMut::map_mut(&file)?;
+        let base = map.as_mut_ptr();
+        let (dirty, failed) = (AtomicBool::new(false), Mutex::new(None));
+        let mut mapped = Mapped { file, map, base, header, dirty, failed, node: PhantomData };
+        if created {
+            mapped.sync(0, 0)?;
+        }
+        mapped.check_header()?;
+        Ok(mapped)
+    }
+
+    fn check_header(&self) -> io::Result<()> {
+        let h = &self.header;
+        let invalid = |msg| Err(Error::new(ErrorKind::InvalidData, msg));
+        if h.magic != MAGIC {
+            return invalid("not a tree file");
+        }
+        if h.version != VERSION {
+            return invalid("unsupported tree file version");
+        }
+        if h.node_size as usize != Self::NODE_SIZE || h.node_align as usize != align_of::<N>() {
+            return invalid("tree file was written with another node type");
+        }
+        if h.dirty != 0 {
+            return invalid("tree file was modified and not flushed");
+        }
+        if h.used > self.slots() || h.size > h.used {
+            return invalid("tree file is truncated");
+        }
+        if h.root != 0 && !self.is_slot(h.root) {
+            return invalid("tree file is corrupted");
+        }
+        Ok(())
+    }
+
+    fn slots(&self) -> u64 {
+        ((self.map.len() - HEADER) / Self::NODE_SIZE) as u64
+    }
+
+    fn grow(&mut self) -> io::Result<()> {
+        let slots = (self.slots() as usize * 2).max(MIN_SLOTS);
+        self.file.set_len((HEADER + slots * Self::NODE_SIZE) as u64)?;
+        self.map = unsafe { MmapMut::map_mut(&self.file)? };
+        self.base = self.map.as_mut_ptr();
+        Ok(())
+    }
+
+    // Writes the nodes to the disk, and only then the header which records
+    // the root and marks the file as unmodified.
+    fn sync(&mut self, root: u64, size: usize) -> io::Result<()> {
+        if let Some(err) = self.failed.get_mut().unwrap_or_else(PoisonError::into_inner).take() {
+            return Err(err);
+        }
+        self.map.flush()?;
+        self.header.root = root;
+        self.header.size = size as u64;
+        unsafe { ptr::write_unaligned(self.base as *mut Header, self.header) };
+        self.map.flush_range(0, HEADER)?;
+        *self.dirty.get_mut() = false;
+        Ok(())
+    }
+
+    // Marks the file as modified on the disk before the first change to it
+    // since it was opened or synced. A failure is reported by the next sync.
+    fn mark_dirty(&self) {
+        if self.dirty.load(Ordering::Acquire) {
+            return;
+        }
+        let mut failed = self.failed.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.dirty.load(Ordering::Relaxed) {
+            return;
+        }
+        unsafe { ptr::write_unaligned(self.base.add(offset_of!(Header, dirty)) as *mut u32, 1) };
+        if let Err(err) = self.map.flush_range(0, HEADER) {
+            *failed = Some(err);
+        }
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    fn next_slot(&mut self) -> io::Result<u64> {
+        if self.header.free != 0 {
+            let offset = self.header.free;
+            self.header.free = unsafe { (self.slot(offset) as *const u64).read_unaligned() };
+            return Ok(offset);
+        }
+        if self.header.used == self.slots() {
+            self.grow()?;
+        }
+        self.header.used += 1;
+        Ok((HEADER + (self.header.used as usize - 1) * Self::NODE_SIZE) as u64)
+    }
+
+    fn is_slot(&self, offset: u64) -> bool {
+        let offset = match offset.checked_sub(HEADER as u64) {
+            Some(offset) => offset,
+            None => return false
+        };
+        offset / (Self::NODE_SIZE as u64) < self.header.used && offset % Self::NODE_SIZE as u64 == 0
+    }
+
+    // The address of a node slot. Offsets read from the file are checked, so
+    // a corrupted file can't lead outside of the mapping.
+    fn slot(&self, offset: u64) -> *mut N {
+        assert!(self.is_slot(offset), "invalid node offset {}", offset);
+        unsafe { self.base.add(offset as usize) as *mut N }
+    }
+}
+
+unsafe impl<N: Node + Plain> NodeAlloc<N> for Mapped<N> where N::Ptr: OffsetNodePtr<N> {
+    /// # Panics
+    ///
+    /// Panics if the file can't be grown to make room for the node.
+    fn alloc(&mut self, node: N) -> N::Ptr {
+        let offset = self.next_slot().expect("failed to grow the tree file");
+        self.mark_dirty();
+        unsafe { self.slot(offset).write(node) };
+        N::Ptr::from_offset(offset)
+    }
+
+    fn try_alloc(&mut self, node: N) -> Result<N::Ptr, AllocError<N>> {
+        match self.next_slot() {
+            Ok(offset) => {
+                self.mark_dirty();
+                unsafe { self.slot(offset).write(node) };
+                Ok(N::Ptr::from_offset(offset))
+            }
+            Err(_) => Err(AllocError::new(node))
+        }
+    }
+
+    unsafe fn free(&mut self, ptr: N::Ptr) -> N {
+        let slot = self.slot(ptr.offset());
+        self.mark_dirty();
+        let node = slot.read();
+        (slot as *mut u64).write_unaligned(self.header.free);
+        self.header.free = ptr.offset();
+        node
+    }
+
+    unsafe fn node(&self, ptr: N::Ptr) -> &N {
+        &*self.slot(ptr.offset())
+    }
+
+    unsafe fn node_mut(&self, ptr: N::Ptr) -> &mut N {
+        self.mark_dirty();
+        &mut *self.slot(ptr.offset())
+    }
+
+    // Dropping can't fail, so an error here is lost, and the file is left
+    // marked as modified.
+    fn drop_tree(&mut self, root: N::Ptr, size: usize) -> bool {
+        let _ = self.sync(root.offset(), size);
+        false
+    }
+}
+
+impl<N: Node + Plain> RBTree<N, Mapped<N>> where N::Ptr: OffsetNodePtr<N> {
+    /// Opens the tree stored in a file, creating an empty one if the file
+    /// does not exist or is empty. Fails with `ErrorKind::InvalidData` if
+    /// the tree was modified and then neither flushed nor dropped.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified by any other means, including another
+    /// open tree, until the tree is dropped.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let alloc = Mapped::<N>::open(path.as_ref())?;
+        let root = alloc.header.root;
+        let size = alloc.header.size as usize;
+        let mut tree = RBTree::with_alloc(alloc);
+        tree.root = N::Ptr::from_offset(root);
+        tree.size = size;
+        Ok(tree)
+    }
+
+    /// Records the root of the tree in the file and writes all the changes
+    /// through to the disk.
+    ///
+    /// Dropping the tree does the same, but can't report an error, so call
+    /// this before dropping it to know whether the changes were written.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.alloc.sync(self.root.offset(), self.size)
+    }
+}
+
+/// A key value node of a tree stored in a file by `Mapped`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MappedNode<K: Plain, V: Plain> {
+    left: MappedNodePtr<K, V>,
+    right: MappedNodePtr<K, V>,
+    key: K,
+    value: V,
+    black: u8
+}
+
+impl<K: Plain, V: Plain> MappedNode<K, V> {
+    pub fn new(key: K, value: V) -> MappedNode<K, V> {
+        MappedNode {
+            left: MappedNodePtr(0, PhantomData),
+            right: MappedNodePtr(0, PhantomData),
+            key,
+            value,
+            black: 0
+        }
+    }
+
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+}
+
+unsafe impl<K: Plain, V: Plain> Plain for MappedNode<K, V> {}
+
+unsafe impl<K: Plain + Ord + Debug, V: Plain> Node for MappedNode<K, V> {
+    type Key = K;
+    type Ptr = MappedNodePtr<K, V>;
+
+    fn left(&self) -> Self::Ptr {
+        self.left
+    }
+
+    fn set_left(&mut self, ptr: Self::Ptr) {
+        self.left = ptr
+    }
+
+    fn right(&self) -> Self::Ptr {
+        self.right
+    }
+
+    fn set_right(&mut self, ptr: Self::Ptr) {
+        self.right = ptr
+    }
+
+    fn key(&self) -> &Self::Key {
+        &self.key
+    }
+
+    fn is_black(&self) -> bool {
+        self.black != 0
+    }
+
+    fn set_black(&mut self) {
+        self.black = 1
+    }
+
+    fn set_red(&mut self) {
+        self.black = 0
+    }
+}
+
+impl<K: Plain + Ord + Debug, V: Plain> SetKey for MappedNode<K, V> {
+    fn set_key(&mut self, key: Self::Key) -> Self::Key {
+        core::mem::replace(&mut self.key, key)
+    }
+}
+
+#[repr(transparent)]
+pub struct MappedNodePtr<K, V>(u64, PhantomData<fn() -> (K, V)>);
+
+impl<K, V> Clone for MappedNodePtr<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for MappedNodePtr<K, V> {}
+
+impl<K: Plain + Ord + Debug, V: Plain> NodePtr<MappedNode<K, V>> for MappedNodePtr<K, V> {
+    const NIL: Self = MappedNodePtr(0, PhantomData);
+
+    fn is_nil(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+unsafe impl<K: Plain + Ord + Debug, V: Plain> OffsetNodePtr<MappedNode<K, V>> for MappedNodePtr<K, V> {
+    fn from_offset(offset: u64) -> Self {
+        MappedNodePtr(offset, PhantomData)
+    }
+
+    fn offset(self) -> u64 {
+        self.0
+    }
+}
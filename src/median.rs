@@ -0,0 +1,72 @@
+use crate::{AggregateNode, Heap, Node, NodeAlloc, RBTree, Sum};
+use crate::kv::Key;
+
+pub type MedianNode<K> = AggregateNode<K, usize, Sum<usize>>;
+
+/// A multiset of keys which answers quantile queries in logarithmic time,
+/// e.g. the median of a sliding window whose keys are inserted as they
+/// arrive and removed as they expire.
+///
+/// Every distinct key is stored once, with the number of times it was
+/// inserted as its value, so repeated keys don't grow the tree.
+pub struct RunningMedian<K: Key, A: NodeAlloc<MedianNode<K>> = Heap> {
+    tree: RBTree<MedianNode<K>, A>
+}
+
+impl<K: Key, A: NodeAlloc<MedianNode<K>> + Default> Default for RunningMedian<K, A> {
+    fn default() -> Self {
+        Self::with_alloc(A::default())
+    }
+}
+
+impl<K: Key> RunningMedian<K> where Heap: NodeAlloc<MedianNode<K>> {
+    pub fn new() -> RunningMedian<K> {
+        Self::with_alloc(Heap)
+    }
+}
+
+impl<K: Key, A: NodeAlloc<MedianNode<K>>> RunningMedian<K, A> {
+    pub fn with_alloc(alloc: A) -> RunningMedian<K, A> {
+        RunningMedian { tree: RBTree::with_alloc(alloc) }
+    }
+
+    /// The number of keys, counting each as many times as it was inserted.
+    pub fn len(&self) -> usize {
+        self.tree.range_count(..)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.size() == 0
+    }
+
+    pub fn insert(&mut self, key: K) {
+        let count = self.tree.search(&key).map_or(0, |node| *node.value());
+        self.tree.insert_owned(MedianNode::new(key, count + 1));
+    }
+
+    /// Removes one occurrence of the key, returning whether there was any.
+    pub fn remove(&mut self, key: &K) -> bool {
+        match self.tree.search(key).map(|node| *node.value()) {
+            None => false,
+            Some(1) => self.tree.delete(key),
+            Some(count) => {
+                self.tree.insert_owned(MedianNode::new(key.clone(), count - 1));
+                true
+            }
+        }
+    }
+
+    /// Returns the `q` quantile of the keys, as in `RBTree::quantile`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `q` is not within `0.0..=1.0`.
+    pub fn quantile(&self, q: f64) -> Option<&K> {
+        self.tree.quantile(q).map(|node| node.key())
+    }
+
+    /// Returns the median key, the lower one of the two for an even count.
+    pub fn median(&self) -> Option<&K> {
+        self.quantile(0.5)
+    }
+}
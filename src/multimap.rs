@@ -0,0 +1,104 @@
+use crate::{Cursor, CursorMut, Heap, Node, NodeAlloc, RBTree};
+use crate::kv::{Key, KeyValue, Value};
+
+pub type MultiMapEntry<K, V> = KeyValue<(K, u64), V>;
+
+/// A map which allows multiple values under the same key.
+///
+/// Every entry is stored in a `RBTree` under its key paired with a sequence
+/// number taken at insertion, so values sharing a key are kept in insertion
+/// order and can be reached with a single descent to the first of them.
+pub struct RBMultiMap<K: Key, V: Value, A: NodeAlloc<MultiMapEntry<K, V>> = Heap> {
+    pub(crate) tree: RBTree<MultiMapEntry<K, V>, A>,
+    pub(crate) seq: u64
+}
+
+impl<K: Key, V: Value, A: NodeAlloc<MultiMapEntry<K, V>> + Default> Default for RBMultiMap<K, V, A> {
+    fn default() -> Self {
+        Self::with_alloc(A::default())
+    }
+}
+
+impl<K: Key, V: Value> RBMultiMap<K, V> {
+    pub fn new() -> RBMultiMap<K, V> {
+        Self::with_alloc(Heap)
+    }
+}
+
+impl<K: Key, V: Value, A: NodeAlloc<MultiMapEntry<K, V>>> RBMultiMap<K, V, A> {
+    pub fn with_alloc(alloc: A) -> RBMultiMap<K, V, A> {
+        RBMultiMap {
+            tree: RBTree::with_alloc(alloc),
+            seq: 0
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    /// Adds a value after all the values already stored under the key.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.tree.insert_owned(KeyValue::new((key, self.seq), value));
+        self.seq += 1;
+    }
+
+    /// Returns the values stored under the key in insertion order.
+    pub fn get_all(&self, key: &K) -> GetAll<'_, K, V, A> {
+        GetAll {
+            cursor: self.tree.cursor_at(&(key.clone(), 0)),
+            key: key.clone()
+        }
+    }
+
+    /// Counts the values stored under the key by walking over them, which
+    /// takes O(log n + k) for `k` values.
+    pub fn count(&self, key: &K) -> usize {
+        self.get_all(key).count()
+    }
+
+    /// Removes the oldest value stored under the key and returns it.
+    pub fn remove_one(&mut self, key: &K) -> Option<V> {
+        let mut cursor = self.cursor_at_mut(key);
+        if !Self::at_key(&cursor, key) {
+            return None;
+        }
+        cursor.remove_current().map(|node| node.into_key_value().1)
+    }
+
+    /// Removes all the values stored under the key and returns how many there
+    /// were.
+    pub fn remove_all(&mut self, key: &K) -> usize {
+        let mut cursor = self.cursor_at_mut(key);
+        let mut removed = 0;
+        while Self::at_key(&cursor, key) {
+            cursor.remove_current();
+            removed += 1;
+        }
+        removed
+    }
+
+    fn cursor_at_mut(&mut self, key: &K) -> CursorMut<'_, MultiMapEntry<K, V>, A> {
+        self.tree.cursor_at_mut(&(key.clone(), 0))
+    }
+
+    fn at_key(cursor: &CursorMut<'_, MultiMapEntry<K, V>, A>, key: &K) -> bool {
+        cursor.current().is_some_and(|node| node.key().0 == *key)
+    }
+}
+
+/// An iterator over the values stored under a key of a `RBMultiMap`.
+pub struct GetAll<'a, K: Key, V: Value, A: NodeAlloc<MultiMapEntry<K, V>> = Heap> {
+    cursor: Cursor<'a, MultiMapEntry<K, V>, A>,
+    key: K
+}
+
+impl<'a, K: Key, V: Value, A: NodeAlloc<MultiMapEntry<K, V>>> Iterator for GetAll<'a, K, V, A> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        let node = self.cursor.current().filter(|node| node.key().0 == self.key)?;
+        self.cursor.move_next();
+        Some(node.value())
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{Cursor, Heap, Node, NodeAlloc, RBTree};
+
+/// A measure of how far apart two keys are, for `RBTree::nearest`. It should
+/// agree with the order of the keys: a key is no closer to another one than
+/// any key between them.
+pub trait Distance {
+    type Output: Ord;
+
+    fn distance(&self, other: &Self) -> Self::Output;
+}
+
+macro_rules! distance {
+    ($($key:ty => $output:ty),*) => {
+        $(impl Distance for $key {
+            type Output = $output;
+
+            fn distance(&self, other: &Self) -> $output {
+                self.abs_diff(*other)
+            }
+        })*
+    };
+}
+
+distance!(u8 => u8, u16 => u16, u32 => u32, u64 => u64, u128 => u128, usize => usize,
+    i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128, isize => usize);
+
+impl<N: Node, A: NodeAlloc<N>> RBTree<N, A> where N::Key: Distance {
+    /// Returns the `k` nodes whose keys are closest to `key`, nearest first,
+    /// walking outward from where the key would be. Of two keys at the same
+    /// distance, the lesser comes first.
+    pub fn nearest<'a>(&'a self, key: &'a N::Key, k: usize) -> Nearest<'a, N, A> {
+        let after = self.cursor_at(key);
+        let mut before = self.cursor_at(key);
+        before.move_prev();
+        Nearest { key, before, after, left: k }
+    }
+}
+
+/// An iterator over the nodes of a `RBTree` closest to a key, returned by
+/// `RBTree::nearest`.
+pub struct Nearest<'a, N: Node, A: NodeAlloc<N> = Heap> {
+    key: &'a N::Key,
+    // Moving backward from the last node before the key, and forward from
+    // the first one from it on. Each stops at the ghost position.
+    before: Cursor<'a, N, A>,
+    after: Cursor<'a, N, A>,
+    left: usize
+}
+
+impl<'a, N: Node, A: NodeAlloc<N>> Iterator for Nearest<'a, N, A> where N::Key: Distance {
+    type Item = &'a N;
+
+    fn next(&mut self) -> Option<&'a N> {
+        if self.left == 0 {
+            return None;
+        }
+        let backward = match (self.before.current(), self.after.current()) {
+            (None, None) => return None,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (Some(before), Some(after)) => {
+                before.key().distance(self.key) <= after.key().distance(self.key)
+            }
+        };
+        self.left -= 1;
+        if backward {
+            let node = self.before.current();
+            self.before.move_prev();
+            node
+        } else {
+            let node = self.after.current();
+            self.after.move_next();
+            node
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+use crate::{Cursor, Heap, Node, NodeAlloc, RBTree};
+
+/// Keys which can be matched against a prefix of type `P`. The keys with the
+/// same prefix must be contiguous in key order.
+pub trait HasPrefix<P: ?Sized> {
+    /// Orders the key against the keys with the prefix: `Equal` if it has the
+    /// prefix, and otherwise `Less` or `Greater` if it comes before or after
+    /// all of them.
+    fn cmp_prefix(&self, prefix: &P) -> Ordering;
+}
+
+// A sequence either starts with the prefix or compares with the keys which do
+// as it compares with the prefix itself.
+fn cmp_slice_prefix<T: Ord>(key: &[T], prefix: &[T]) -> Ordering {
+    if key.starts_with(prefix) { Ordering::Equal } else { key.cmp(prefix) }
+}
+
+impl HasPrefix<str> for String {
+    fn cmp_prefix(&self, prefix: &str) -> Ordering {
+        cmp_slice_prefix(self.as_bytes(), prefix.as_bytes())
+    }
+}
+
+impl HasPrefix<str> for &str {
+    fn cmp_prefix(&self, prefix: &str) -> Ordering {
+        cmp_slice_prefix(self.as_bytes(), prefix.as_bytes())
+    }
+}
+
+impl<T: Ord> HasPrefix<[T]> for Vec<T> {
+    fn cmp_prefix(&self, prefix: &[T]) -> Ordering {
+        cmp_slice_prefix(self, prefix)
+    }
+}
+
+impl<T: Ord> HasPrefix<[T]> for &[T] {
+    fn cmp_prefix(&self, prefix: &[T]) -> Ordering {
+        cmp_slice_prefix(self, prefix)
+    }
+}
+
+/// Composite keys, matched by their first field.
+impl<A: Ord, B> HasPrefix<A> for (A, B) {
+    fn cmp_prefix(&self, prefix: &A) -> Ordering {
+        self.0.cmp(prefix)
+    }
+}
+
+/// Composite keys, matched by their first field.
+impl<A: Ord, B, C> HasPrefix<A> for (A, B, C) {
+    fn cmp_prefix(&self, prefix: &A) -> Ordering {
+        self.0.cmp(prefix)
+    }
+}
+
+/// Composite keys, matched by their first two fields.
+impl<A: Ord, B: Ord, C> HasPrefix<(A, B)> for (A, B, C) {
+    fn cmp_prefix(&self, prefix: &(A, B)) -> Ordering {
+        (&self.0, &self.1).cmp(&(&prefix.0, &prefix.1))
+    }
+}
+
+impl<N: Node, A: NodeAlloc<N>> RBTree<N, A> {
+    /// Iterates in key order over the nodes whose keys have the prefix,
+    /// descending once to the first of them.
+    pub fn prefix_iter<'a, P: ?Sized>(&'a self, prefix: &'a P) -> PrefixIter<'a, N, P, A> where N::Key: HasPrefix<P> {
+        let cursor = self.cursor_partition(|key| key.cmp_prefix(prefix) == Ordering::Less);
+        PrefixIter { cursor, prefix }
+    }
+}
+
+/// An iterator over the nodes of a `RBTree` whose keys have a prefix,
+/// returned by `RBTree::prefix_iter`.
+pub struct PrefixIter<'a, N: Node, P: ?Sized, A: NodeAlloc<N> = Heap> {
+    cursor: Cursor<'a, N, A>,
+    prefix: &'a P
+}
+
+impl<'a, N: Node, A: NodeAlloc<N>, P: ?Sized> Iterator for PrefixIter<'a, N, P, A> where N::Key: HasPrefix<P> {
+    type Item = &'a N;
+
+    fn next(&mut self) -> Option<&'a N> {
+        let node = self.cursor.current().filter(|node| node.key().cmp_prefix(self.prefix) == Ordering::Equal)?;
+        self.cursor.move_next();
+        Some(node)
+    }
+}
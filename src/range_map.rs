@@ -0,0 +1,155 @@
+use core::ops::Range;
+
+use crate::{Cursor, Heap, Node, NodeAlloc, RBTree};
+use crate::kv::{Key, KeyValue, Value};
+
+/// A range `[start, end)` of a `RangeMap` with its value, stored under the
+/// start.
+pub type RangeEntry<K, V> = KeyValue<K, (K, V)>;
+
+/// A map from non-overlapping half-open ranges of keys to values.
+///
+/// Ranges written over others cut them short, or split them in two, and
+/// adjacent ranges with equal values are merged, so every point is covered
+/// by at most one range and the map holds as few ranges as its content
+/// allows.
+pub struct RangeMap<K: Key, V: Value, A: NodeAlloc<RangeEntry<K, V>> = Heap> {
+    pub(crate) tree: RBTree<RangeEntry<K, V>, A>
+}
+
+impl<K: Key, V: Value, A: NodeAlloc<RangeEntry<K, V>> + Default> Default for RangeMap<K, V, A> {
+    fn default() -> Self {
+        Self::with_alloc(A::default())
+    }
+}
+
+impl<K: Key, V: Value> RangeMap<K, V> {
+    pub fn new() -> RangeMap<K, V> {
+        Self::with_alloc(Heap)
+    }
+}
+
+impl<K: Key, V: Value, A: NodeAlloc<RangeEntry<K, V>>> RangeMap<K, V, A> {
+    pub fn with_alloc(alloc: A) -> RangeMap<K, V, A> {
+        RangeMap { tree: RBTree::with_alloc(alloc) }
+    }
+
+    /// The number of ranges, after merging.
+    pub fn len(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the value of the range containing `point`.
+    pub fn get(&self, point: &K) -> Option<&V> {
+        self.get_range(point).map(|(_, _, value)| value)
+    }
+
+    /// Returns the start, end and value of the range containing `point`.
+    pub fn get_range(&self, point: &K) -> Option<(&K, &K, &V)> {
+        let mut cursor = self.tree.cursor_at(point);
+        if cursor.current().is_none_or(|node| node.key() != point) {
+            cursor.move_prev();
+        }
+        let node = cursor.current()?;
+        let (end, value) = node.value();
+        if node.key() <= point && point < end { Some((node.key(), end, value)) } else { None }
+    }
+
+    /// Iterates over the start, end and value of the ranges in order.
+    pub fn iter(&self) -> Ranges<'_, K, V, A> {
+        Ranges { cursor: self.tree.cursor_front(), len: self.len() }
+    }
+}
+
+impl<K: Key, V: Value + Clone, A: NodeAlloc<RangeEntry<K, V>>> RangeMap<K, V, A> {
+    /// Clears `range`, cutting short the ranges which overlap it and
+    /// splitting one which covers it.
+    pub fn remove(&mut self, range: Range<K>) {
+        let Range { start, end } = range;
+        if start >= end {
+            return;
+        }
+        let mut cursor = self.tree.cursor_at_mut(&start);
+        let mut kept = None;
+        let mut tail = None;
+        cursor.move_prev();
+        if let Some(node) = cursor.current() {
+            let (before_end, value) = node.value();
+            if *before_end > start {
+                kept = Some(RangeEntry::new(node.key().clone(), (start.clone(), value.clone())));
+                if *before_end > end {
+                    tail = Some(RangeEntry::new(end.clone(), (before_end.clone(), value.clone())));
+                }
+            }
+        }
+        cursor.move_next();
+        while let Some(node) = cursor.current().filter(|node| node.key() < &end) {
+            let (after_end, value) = node.value();
+            if *after_end > end {
+                tail = Some(RangeEntry::new(end.clone(), (after_end.clone(), value.clone())));
+            }
+            cursor.remove_current();
+        }
+        for entry in kept.into_iter().chain(tail) {
+            self.tree.insert_owned(entry);
+        }
+    }
+}
+
+impl<K: Key, V: Value + Clone + PartialEq, A: NodeAlloc<RangeEntry<K, V>>> RangeMap<K, V, A> {
+    /// Maps every point of `range` to `value`, overwriting what was there and
+    /// merging with the adjacent ranges of an equal value.
+    pub fn insert(&mut self, range: Range<K>, value: V) {
+        if range.start >= range.end {
+            return;
+        }
+        self.remove(range.clone());
+        let Range { mut start, mut end } = range;
+        let mut cursor = self.tree.cursor_at_mut(&start);
+        cursor.move_prev();
+        if let Some(node) = cursor.current() {
+            let (before_end, before) = node.value();
+            if *before_end == start && *before == value {
+                start = node.key().clone();
+                cursor.remove_current();
+            }
+        }
+        let mut cursor = self.tree.cursor_at_mut(&end);
+        if let Some(node) = cursor.current() {
+            let (after_end, after) = node.value();
+            if *node.key() == end && *after == value {
+                end = after_end.clone();
+                cursor.remove_current();
+            }
+        }
+        self.tree.insert_owned(RangeEntry::new(start, (end, value)));
+    }
+}
+
+/// An iterator over the ranges of a `RangeMap`, in order.
+pub struct Ranges<'a, K: Key, V: Value, A: NodeAlloc<RangeEntry<K, V>> = Heap> {
+    cursor: Cursor<'a, RangeEntry<K, V>, A>,
+    len: usize
+}
+
+impl<'a, K: Key, V: Value, A: NodeAlloc<RangeEntry<K, V>>> Iterator for Ranges<'a, K, V, A> {
+    type Item = (&'a K, &'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.cursor.current()?;
+        self.cursor.move_next();
+        self.len -= 1;
+        let (end, value) = node.value();
+        Some((node.key(), end, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, K: Key, V: Value, A: NodeAlloc<RangeEntry<K, V>>> ExactSizeIterator for Ranges<'a, K, V, A> {}
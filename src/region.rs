@@ -0,0 +1,327 @@
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::io::{self, Error, ErrorKind};
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+
+use crate::{AllocError, Node, NodeAlloc, NodePtr, Plain, RBTree, SetKey};
+
+/// A key value node whose links are 32-bit offsets relative to the address
+/// of the links themselves, so that nodes within 2 GiB of each other link to
+/// each other wherever the memory holding them is mapped.
+#[repr(C)]
+pub struct RelNode<K: Plain, V: Plain> {
+    left: i32,
+    right: i32,
+    key: K,
+    value: V,
+    black: u8
+}
+
+impl<K: Plain, V: Plain> RelNode<K, V> {
+    pub fn new(key: K, value: V) -> RelNode<K, V> {
+        RelNode {
+            left: 0,
+            right: 0,
+            key,
+            value,
+            black: 0
+        }
+    }
+
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    // An offset of 0 would be a link to itself, so it stands for NIL.
+    fn link(field: &i32) -> RelNodePtr<K, V> {
+        if *field == 0 {
+            RelNodePtr(0, PhantomData)
+        } else {
+            RelNodePtr((field as *const i32 as usize).wrapping_add(*field as isize as usize), PhantomData)
+        }
+    }
+
+    fn set_link(field: &mut i32, ptr: RelNodePtr<K, V>) {
+        *field = if ptr.0 == 0 {
+            0
+        } else {
+            let offset = ptr.0.wrapping_sub(field as *mut i32 as usize) as isize;
+            i32::try_from(offset).expect("node out of reach of a relative link")
+        }
+    }
+}
+
+impl<K: Plain, V: Plain> Clone for RelNode<K, V> {
+    fn clone(&self) -> Self {
+        RelNode::new(self.key, self.value)
+    }
+}
+
+unsafe impl<K: Plain + Ord + Debug, V: Plain> Node for RelNode<K, V> {
+    type Key = K;
+    type Ptr = RelNodePtr<K, V>;
+
+    fn left(&self) -> Self::Ptr {
+        Self::link(&self.left)
+    }
+
+    fn set_left(&mut self, ptr: Self::Ptr) {
+        Self::set_link(&mut self.left, ptr)
+    }
+
+    fn right(&self) -> Self::Ptr {
+        Self::link(&self.right)
+    }
+
+    fn set_right(&mut self, ptr: Self::Ptr) {
+        Self::set_link(&mut self.right, ptr)
+    }
+
+    fn key(&self) -> &Self::Key {
+        &self.key
+    }
+
+    fn is_black(&self) -> bool {
+        self.black != 0
+    }
+
+    fn set_black(&mut self) {
+        self.black = 1
+    }
+
+    fn set_red(&mut self) {
+        self.black = 0
+    }
+}
+
+impl<K: Plain + Ord + Debug, V: Plain> SetKey for RelNode<K, V> {
+    fn set_key(&mut self, key: Self::Key) -> Self::Key {
+        core::mem::replace(&mut self.key, key)
+    }
+}
+
+/// The address of a `RelNode` in the current process.
+pub struct RelNodePtr<K, V>(usize, PhantomData<fn() -> (K, V)>);
+
+impl<K, V> Clone for RelNodePtr<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for RelNodePtr<K, V> {}
+
+impl<K: Plain + Ord + Debug, V: Plain> NodePtr<RelNode<K, V>> for RelNodePtr<K, V> {
+    const NIL: Self = RelNodePtr(0, PhantomData);
+
+    fn is_nil(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+const MAGIC: [u8; 8] = *b"RBREGION";
+const HEADER: usize = 64;
+
+#[repr(C)]
+struct Header {
+    magic: [u8; 8],
+    node_size: u32,
+    node_align: u32,
+    // Offsets from the start of the region, which mean the same in every
+    // process mapping it. Released slots are chained through their first
+    // 8 bytes.
+    root: u64,
+    size: u64,
+    used: u64,
+    free: u64
+}
+
+/// Allocates nodes from a fixed region of memory, such as shared memory,
+/// keeping all its bookkeeping and the root of its tree within the region.
+///
+/// A tree built with `RelNode`s in a region can be read from another mapping
+/// of the same memory, possibly at another address in another process,
+/// through `Region::attach` and `RBTree::from_region`.
+pub struct Region<N> {
+    base: *mut u8,
+    len: usize,
+    node: PhantomData<N>
+}
+
+// The memory behind `base` is lent to this region alone by the caller of
+// `init` or `attach` for as long as it is used, whichever thread that is on.
+unsafe impl<N: Send> Send for Region<N> {}
+unsafe impl<N: Sync> Sync for Region<N> {}
+
+impl<N> Region<N> {
+    const NODE_SIZE: usize = size_of::<N>();
+
+    /// Formats `len` bytes of memory at `base` as an empty region.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `base` is not aligned to 8 bytes and for `N`, or if `len`
+    /// is too small for the header or beyond the reach of 32-bit offsets.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be valid for reads and writes for as long as the
+    /// region is used, and may only be accessed through this region while a
+    /// tree over it is being modified, including through other mappings.
+    pub unsafe fn init(base: *mut u8, len: usize) -> Region<N> {
+        let region = Self::new(base, len);
+        *region.header() = Header {
+            magic: MAGIC,
+            node_size: Self::NODE_SIZE as u32,
+            node_align: align_of::<N>() as u32,
+            root: 0,
+            size: 0,
+            used: 0,
+            free: 0
+        };
+        region
+    }
+
+    /// Attaches to memory formatted by `init`, which may have been mapped at
+    /// another address, or by another process.
+    ///
+    /// # Panics
+    ///
+    /// Same as `init`.
+    ///
+    /// # Safety
+    ///
+    /// Same as `init`.
+    pub unsafe fn attach(base: *mut u8, len: usize) -> io::Result<Region<N>> {
+        let region = Self::new(base, len);
+        let h = &*region.header();
+        let invalid = |msg| Err(Error::new(ErrorKind::InvalidData, msg));
+        if h.magic != MAGIC {
+            return invalid("not a tree region");
+        }
+        if h.node_size as usize != Self::NODE_SIZE || h.node_align as usize != align_of::<N>() {
+            return invalid("tree region was written with another node type");
+        }
+        if h.used > region.capacity() as u64 || h.size > h.used || (h.root != 0 && !region.is_slot(h.root)) {
+            return invalid("tree region is corrupted");
+        }
+        Ok(region)
+    }
+
+    fn new(base: *mut u8, len: usize) -> Region<N> {
+        assert!(base.align_offset(align_of::<N>().max(8)) == 0, "region is not aligned");
+        assert!(align_of::<N>() <= HEADER && Self::NODE_SIZE >= size_of::<u64>(), "node type can't be stored in a region");
+        assert!(len >= HEADER && len <= i32::MAX as usize, "invalid region size");
+        Region { base, len, node: PhantomData }
+    }
+
+    /// The number of nodes which fit in the region.
+    pub fn capacity(&self) -> usize {
+        (self.len - HEADER) / Self::NODE_SIZE
+    }
+
+    fn header(&self) -> *mut Header {
+        self.base as *mut Header
+    }
+
+    fn is_slot(&self, offset: u64) -> bool {
+        let offset = match offset.checked_sub(HEADER as u64) {
+            Some(offset) => offset,
+            None => return false
+        };
+        let used = unsafe { (*self.header()).used };
+        let index = offset / Self::NODE_SIZE as u64;
+        index < used && index < self.capacity() as u64 && offset % Self::NODE_SIZE as u64 == 0
+    }
+
+    // The address of a node slot. Links read from the region are checked, so
+    // corrupted memory can't lead outside of the region.
+    fn slot(&self, offset: u64) -> *mut N {
+        assert!(self.is_slot(offset), "invalid node offset {}", offset);
+        unsafe { self.base.add(offset as usize) as *mut N }
+    }
+
+    fn offset(&self, addr: usize) -> u64 {
+        addr.wrapping_sub(self.base as usize) as u64
+    }
+}
+
+unsafe impl<K: Plain + Ord + Debug, V: Plain> NodeAlloc<RelNode<K, V>> for Region<RelNode<K, V>> {
+    /// # Panics
+    ///
+    /// Panics if the region is full.
+    fn alloc(&mut self, node: RelNode<K, V>) -> RelNodePtr<K, V> {
+        match self.try_alloc(node) {
+            Ok(ptr) => ptr,
+            Err(_) => panic!("region is full")
+        }
+    }
+
+    fn try_alloc(&mut self, node: RelNode<K, V>) -> Result<RelNodePtr<K, V>, AllocError<RelNode<K, V>>> {
+        let header = self.header();
+        let offset = unsafe {
+            if (*header).free != 0 {
+                let offset = (*header).free;
+                (*header).free = (self.slot(offset) as *const u64).read_unaligned();
+                offset
+            } else if ((*header).used as usize) < self.capacity() {
+                (*header).used += 1;
+                (HEADER + ((*header).used as usize - 1) * Self::NODE_SIZE) as u64
+            } else {
+                return Err(AllocError::new(node));
+            }
+        };
+        let slot = self.slot(offset);
+        unsafe { slot.write(node) };
+        Ok(RelNodePtr(slot as usize, PhantomData))
+    }
+
+    unsafe fn free(&mut self, ptr: RelNodePtr<K, V>) -> RelNode<K, V> {
+        let offset = self.offset(ptr.0);
+        let slot = self.slot(offset);
+        let node = slot.read();
+        let header = self.header();
+        (slot as *mut u64).write_unaligned((*header).free);
+        (*header).free = offset;
+        node
+    }
+
+    unsafe fn node(&self, ptr: RelNodePtr<K, V>) -> &RelNode<K, V> {
+        &*self.slot(self.offset(ptr.0))
+    }
+
+    unsafe fn node_mut(&self, ptr: RelNodePtr<K, V>) -> &mut RelNode<K, V> {
+        &mut *self.slot(self.offset(ptr.0))
+    }
+
+    fn drop_tree(&mut self, _root: RelNodePtr<K, V>, _size: usize) -> bool {
+        false
+    }
+}
+
+impl<K: Plain + Ord + Debug, V: Plain> RBTree<RelNode<K, V>, Region<RelNode<K, V>>> {
+    /// Creates a tree over a region, holding the nodes last committed to it.
+    pub fn from_region(region: Region<RelNode<K, V>>) -> Self {
+        let header = region.header();
+        let (root, size) = unsafe { ((*header).root, (*header).size as usize) };
+        let mut tree = RBTree::with_alloc(region);
+        if root != 0 {
+            tree.root = RelNodePtr(tree.alloc.slot(root) as usize, PhantomData);
+            tree.size = size;
+        }
+        tree
+    }
+
+    /// Records the root of the tree in the region, so that it is found by
+    /// `from_region`. The nodes stay in the region when the tree is dropped,
+    /// but changes made after the last commit are lost.
+    pub fn commit(&mut self) {
+        let root = if self.root.is_nil() { 0 } else { self.alloc.offset(self.root.0) };
+        let header = self.alloc.header();
+        unsafe {
+            (*header).root = root;
+            (*header).size = self.size as u64;
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use alloc::vec::Vec;
+
+use crate::{Detached, Node, NodeAlloc, NodePtr, PtrExt, RBTree};
+
+impl<N: Node, A: NodeAlloc<N>> RBTree<N, A> {
+    /// Removes every node for which `f` returns false.
+    pub fn retain<F: FnMut(&N) -> bool>(&mut self, mut f: F) {
+        self.extract_if(|node| !f(node)).for_each(drop);
+    }
+
+    /// Removes every node for which `pred` returns true and returns them in
+    /// key order. The nodes are removed from the tree by the time this method
+    /// returns, the iterator only hands them out.
+    pub fn extract_if<F: FnMut(&N) -> bool>(&mut self, pred: F) -> ExtractIf<'_, N, A> {
+        // The unlinked nodes are chained in key order through their right
+        // links, a subtree with no left child to release them one by one.
+        let mut root = N::Ptr::NIL;
+        for ptr in self.unlink_if(pred).into_iter().rev() {
+            let node = ptr.node_mut(&self.alloc);
+            node.set_left(N::Ptr::NIL);
+            node.set_right(root);
+            root = ptr;
+        }
+        ExtractIf { nodes: Detached { alloc: &mut self.alloc, root } }
+    }
+
+    // When more than about one node in `log(n)` has to be removed, deleting
+    // them one by one costs more than relinking the survivors from scratch.
+    fn unlink_if<F: FnMut(&N) -> bool>(&mut self, mut pred: F) -> Vec<N::Ptr> {
+        let nodes = self.in_order();
+        let matches: Vec<bool> = nodes.iter().map(|ptr| pred(ptr.node(&self.alloc))).collect();
+        let count = matches.iter().filter(|m| **m).count();
+        if count == 0 {
+            return Vec::new();
+        }
+        let depth = (usize::BITS - self.size.leading_zeros()) as usize;
+        if count * depth >= self.size {
+            let (unlinked, kept): (Vec<_>, Vec<_>) = nodes.iter()
+                .zip(matches)
+                .partition(|(_, m)| *m);
+            let kept: Vec<N::Ptr> = kept.into_iter().map(|(ptr, _)| *ptr).collect();
+            self.rebuild(&kept);
+            unlinked.into_iter().map(|(ptr, _)| *ptr).collect()
+        } else {
+            let mut unlinked = Vec::with_capacity(count);
+            let mut cursor = self.cursor_front_mut();
+            for m in matches {
+                if m {
+                    unlinked.push(cursor.unlink_current());
+                } else {
+                    cursor.move_next();
+                }
+            }
+            unlinked
+        }
+    }
+}
+
+/// An iterator over the nodes removed by `RBTree::extract_if`.
+pub struct ExtractIf<'a, N: Node, A: NodeAlloc<N>> {
+    nodes: Detached<'a, N, A>
+}
+
+// The unlinked nodes are owned by the iterator until it releases them.
+unsafe impl<'a, N: Node + Send, A: NodeAlloc<N> + Send> Send for ExtractIf<'a, N, A> {}
+unsafe impl<'a, N: Node + Sync, A: NodeAlloc<N> + Sync> Sync for ExtractIf<'a, N, A> {}
+
+impl<'a, N: Node, A: NodeAlloc<N>> Iterator for ExtractIf<'a, N, A> {
+    type Item = N;
+
+    fn next(&mut self) -> Option<N> {
+        self.nodes.next()
+    }
+}
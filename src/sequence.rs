@@ -0,0 +1,298 @@
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Formatter};
+use core::iter::FromIterator;
+use core::ptr::null_mut;
+
+use crate::hint::Route;
+use crate::{Heap, Node, NodeAlloc, NodePtr, PtrExt, RawNodePtr, RBTree};
+
+/// A node of a `Sequence`. It has no key, nodes are ordered by position,
+/// which is found from the number of nodes in every subtree.
+pub struct SeqNode<T> {
+    left: SeqNodePtr<T>,
+    right: SeqNodePtr<T>,
+    black: bool,
+    pub(crate) count: usize,
+    value: T
+}
+
+impl<T> SeqNode<T> {
+    fn new(value: T) -> SeqNode<T> {
+        SeqNode { left: SeqNodePtr::NIL, right: SeqNodePtr::NIL, black: false, count: 1, value }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+}
+
+unsafe impl<T> Node for SeqNode<T> {
+    type Key = ();
+    type Ptr = SeqNodePtr<T>;
+
+    fn left(&self) -> Self::Ptr {
+        self.left
+    }
+
+    fn set_left(&mut self, ptr: Self::Ptr) {
+        self.left = ptr
+    }
+
+    fn right(&self) -> Self::Ptr {
+        self.right
+    }
+
+    fn set_right(&mut self, ptr: Self::Ptr) {
+        self.right = ptr
+    }
+
+    fn key(&self) -> &() {
+        &()
+    }
+
+    fn is_black(&self) -> bool {
+        self.black
+    }
+
+    fn set_black(&mut self) {
+        self.black = true
+    }
+
+    fn set_red(&mut self) {
+        self.black = false
+    }
+
+    const AUGMENTED: bool = true;
+
+    fn refresh(&mut self, left: Option<&Self>, right: Option<&Self>) {
+        self.count = 1 + left.map_or(0, |n| n.count) + right.map_or(0, |n| n.count);
+    }
+}
+
+// The links of a sequence node are only dereferenced by the `Sequence` owning
+// it, through the allocator of its tree, so only the element decides whether
+// the node can cross threads. The subtree count is a plain `usize`.
+unsafe impl<T: Send> Send for SeqNode<T> {}
+unsafe impl<T: Sync> Sync for SeqNode<T> {}
+
+pub struct SeqNodePtr<T>(*mut SeqNode<T>);
+
+impl<T> Clone for SeqNodePtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SeqNodePtr<T> {}
+
+impl<T> NodePtr<SeqNode<T>> for SeqNodePtr<T> {
+    const NIL: Self = SeqNodePtr(null_mut());
+
+    fn is_nil(&self) -> bool {
+        self.0.is_null()
+    }
+}
+
+unsafe impl<T> RawNodePtr<SeqNode<T>> for SeqNodePtr<T> {
+    fn from_raw(ptr: *mut SeqNode<T>) -> Self {
+        SeqNodePtr(ptr)
+    }
+
+    fn into_raw(self) -> *mut SeqNode<T> {
+        self.0
+    }
+}
+
+/// A list of values with insertion, removal and access by position in
+/// logarithmic time, kept in a `RBTree` of `SeqNode`s.
+pub struct Sequence<T, A: NodeAlloc<SeqNode<T>> = Heap> {
+    pub(crate) tree: RBTree<SeqNode<T>, A>
+}
+
+impl<T> Default for Sequence<T> {
+    fn default() -> Self {
+        Sequence::new()
+    }
+}
+
+impl<T> Sequence<T> {
+    pub fn new() -> Sequence<T> {
+        Sequence::with_alloc(Heap)
+    }
+
+    /// Splits the sequence into the values before `index` and the rest, in
+    /// logarithmic time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the length.
+    pub fn split_at(mut self, index: usize) -> (Sequence<T>, Sequence<T>) {
+        let len = self.len();
+        assert!(index <= len, "split index (is {}) should be <= len (is {})", index, len);
+        let tree = self.tree.take_subtree();
+        let alloc = &self.tree.alloc;
+        let mut rest = index;
+        let (before, after) = RBTree::split(alloc, tree, &mut |node: &SeqNode<T>| {
+            let left = count(alloc, node.left());
+            if rest <= left {
+                true
+            } else {
+                rest -= left + 1;
+                false
+            }
+        });
+        let (mut head, mut tail) = (Sequence::new(), Sequence::new());
+        head.tree.put_subtree(before, index);
+        tail.tree.put_subtree(after, len - index);
+        (head, tail)
+    }
+}
+
+impl<T, A: NodeAlloc<SeqNode<T>>> Sequence<T, A> {
+    pub fn with_alloc(alloc: A) -> Sequence<T, A> {
+        Sequence { tree: RBTree::with_alloc(alloc) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let ptr = self.find(index);
+        if ptr.is_nil() { None } else { Some(&ptr.node(&self.tree.alloc).value) }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let ptr = self.find(index);
+        if ptr.is_nil() { None } else { Some(&mut ptr.node_mut(&self.tree.alloc).value) }
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.insert_at(self.len(), value);
+    }
+
+    /// Inserts a value at `index`, shifting the values after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the length.
+    pub fn insert_at(&mut self, index: usize, value: T) {
+        let len = self.len();
+        assert!(index <= len, "insertion index (is {}) should be <= len (is {})", index, len);
+        let route = self.route(index, true);
+        let inserted = self.tree.insert_with(SeqNode::new(value), &mut route.probe(), &mut |alloc, node| Ok(alloc.alloc(node)));
+        debug_assert!(matches!(inserted, Ok(None)));
+    }
+
+    /// Removes and returns the value at `index`, shifting the values after it.
+    pub fn remove_at(&mut self, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut directions = self.route(index, false).directions();
+        let removed = self.tree.unlink_by(&mut |_| directions());
+        Some(RBTree::release(&mut self.tree.alloc, removed).value)
+    }
+
+    pub fn iter(&self) -> SeqIter<'_, T, A> {
+        let mut iter = SeqIter { stack: Vec::new(), alloc: &self.tree.alloc, len: self.len() };
+        iter.push_left_most(self.tree.root);
+        iter
+    }
+
+    fn find(&self, mut index: usize) -> SeqNodePtr<T> {
+        let alloc = &self.tree.alloc;
+        let mut ptr = self.tree.root;
+        while !ptr.is_nil() {
+            let node = ptr.node(alloc);
+            let left = count(alloc, node.left());
+            if index < left {
+                ptr = node.left();
+            } else if index == left {
+                break;
+            } else {
+                index -= left + 1;
+                ptr = node.right();
+            }
+        }
+        ptr
+    }
+
+    // The route to the node at `index`, or with `empty`, to the empty slot
+    // where a node inserted at `index` belongs.
+    fn route(&self, mut index: usize, empty: bool) -> Route {
+        let alloc = &self.tree.alloc;
+        let mut route = Route::EMPTY;
+        let mut ptr = self.tree.root;
+        while !ptr.is_nil() {
+            let node = ptr.node(alloc);
+            let left = count(alloc, node.left());
+            if index < left || (empty && index == left) {
+                route.push_back(false);
+                ptr = node.left();
+            } else if index == left {
+                break;
+            } else {
+                index -= left + 1;
+                route.push_back(true);
+                ptr = node.right();
+            }
+        }
+        route
+    }
+}
+
+fn count<T, A: NodeAlloc<SeqNode<T>>>(alloc: &A, ptr: SeqNodePtr<T>) -> usize {
+    if ptr.is_nil() { 0 } else { ptr.node(alloc).count }
+}
+
+impl<T> FromIterator<T> for Sequence<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut sequence = Sequence::new();
+        iter.into_iter().for_each(|value| sequence.push(value));
+        sequence
+    }
+}
+
+impl<T: Debug, A: NodeAlloc<SeqNode<T>>> Debug for Sequence<T, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// An iterator over the values of a `Sequence` in order.
+pub struct SeqIter<'a, T: 'a, A: NodeAlloc<SeqNode<T>>> {
+    stack: Vec<SeqNodePtr<T>>,
+    alloc: &'a A,
+    len: usize
+}
+
+impl<'a, T, A: NodeAlloc<SeqNode<T>>> SeqIter<'a, T, A> {
+    fn push_left_most(&mut self, mut ptr: SeqNodePtr<T>) {
+        while !ptr.is_nil() {
+            self.stack.push(ptr);
+            ptr = ptr.node(self.alloc).left();
+        }
+    }
+}
+
+impl<'a, T: 'a, A: NodeAlloc<SeqNode<T>>> Iterator for SeqIter<'a, T, A> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.stack.pop()?.node(self.alloc);
+        self.push_left_most(node.right());
+        self.len -= 1;
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T: 'a, A: NodeAlloc<SeqNode<T>>> ExactSizeIterator for SeqIter<'a, T, A> {}
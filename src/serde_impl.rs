@@ -0,0 +1,105 @@
+use alloc::vec::Vec;
+use core::fmt::{self, Formatter};
+use core::marker::PhantomData;
+
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::{KeyValue, Node, NodeAlloc, RBMultiMap, RBTree};
+use crate::kv::{Key, Value};
+
+impl<K, V, A> Serialize for RBTree<KeyValue<K, V>, A>
+    where K: Key + Serialize, V: Value + Serialize, A: NodeAlloc<KeyValue<K, V>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.size))?;
+        let mut cursor = self.cursor_front();
+        while let Some(node) = cursor.current() {
+            map.serialize_entry(node.key(), node.value())?;
+            cursor.move_next();
+        }
+        map.end()
+    }
+}
+
+struct TreeVisitor<K, V, A>(PhantomData<(K, V, A)>);
+
+impl<'de, K, V, A> Visitor<'de> for TreeVisitor<K, V, A>
+    where K: Key + Deserialize<'de>, V: Value + Deserialize<'de>, A: NodeAlloc<KeyValue<K, V>> + Default {
+    type Value = RBTree<KeyValue<K, V>, A>;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("a map")
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut access: M) -> Result<Self::Value, M::Error> {
+        let mut nodes: Vec<KeyValue<K, V>> = Vec::with_capacity(access.size_hint().unwrap_or(0).min(4096));
+        while let Some((key, value)) = access.next_entry()? {
+            nodes.push(KeyValue::new(key, value));
+        }
+        // Maps serialized by a tree are already sorted, which makes sorting
+        // linear, and the tree is then linked without any rebalancing.
+        nodes.sort_by(|a, b| a.key().cmp(b.key()));
+        let mut unique: Vec<KeyValue<K, V>> = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            match unique.last_mut() {
+                Some(last) if last.key() == node.key() => *last = node,
+                _ => unique.push(node)
+            }
+        }
+        Ok(RBTree::from_sorted(unique, A::default()))
+    }
+}
+
+/// Deserializes a map. Of entries with equal keys, the last one is kept.
+impl<'de, K, V, A> Deserialize<'de> for RBTree<KeyValue<K, V>, A>
+    where K: Key + Deserialize<'de>, V: Value + Deserialize<'de>, A: NodeAlloc<KeyValue<K, V>> + Default {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(TreeVisitor(PhantomData))
+    }
+}
+
+/// Serializes the entries as a sequence of key value pairs, in key order and
+/// insertion order within a key.
+impl<K, V, A> Serialize for RBMultiMap<K, V, A>
+    where K: Key + Serialize, V: Value + Serialize, A: NodeAlloc<KeyValue<(K, u64), V>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.size()))?;
+        let mut cursor = self.tree.cursor_front();
+        while let Some(node) = cursor.current() {
+            seq.serialize_element(&(&node.key().0, node.value()))?;
+            cursor.move_next();
+        }
+        seq.end()
+    }
+}
+
+struct MultiMapVisitor<K, V, A>(PhantomData<(K, V, A)>);
+
+impl<'de, K, V, A> Visitor<'de> for MultiMapVisitor<K, V, A>
+    where K: Key + Deserialize<'de>, V: Value + Deserialize<'de>, A: NodeAlloc<KeyValue<(K, u64), V>> + Default {
+    type Value = RBMultiMap<K, V, A>;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("a sequence of key value pairs")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut access: S) -> Result<Self::Value, S::Error> {
+        let mut nodes: Vec<KeyValue<(K, u64), V>> = Vec::with_capacity(access.size_hint().unwrap_or(0).min(4096));
+        while let Some((key, value)) = access.next_element()? {
+            nodes.push(KeyValue::new((key, nodes.len() as u64), value));
+        }
+        nodes.sort_by(|a, b| a.key().cmp(b.key()));
+        let seq = nodes.len() as u64;
+        Ok(RBMultiMap {
+            tree: RBTree::from_sorted(nodes, A::default()),
+            seq
+        })
+    }
+}
+
+impl<'de, K, V, A> Deserialize<'de> for RBMultiMap<K, V, A>
+    where K: Key + Deserialize<'de>, V: Value + Deserialize<'de>, A: NodeAlloc<KeyValue<(K, u64), V>> + Default {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(MultiMapVisitor(PhantomData))
+    }
+}
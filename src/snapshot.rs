@@ -0,0 +1,268 @@
+use std::convert::TryFrom;
+use std::io::{self, Error, ErrorKind, Read, Write};
+
+use crate::{KeyValue, Node, NodeAlloc, RBTree};
+use crate::build::Sorted;
+use crate::kv::{Key, Value};
+
+/// A type which can be written to a snapshot and read back.
+///
+/// Numbers are written in little endian, and strings and vectors are
+/// prefixed with their length.
+pub trait Codec: Sized {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()>;
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self>;
+}
+
+fn invalid<T>(msg: &str) -> io::Result<T> {
+    Err(Error::new(ErrorKind::InvalidData, msg))
+}
+
+macro_rules! codec_num {
+    ($($t:ty),*) => {
+        $(impl Codec for $t {
+            fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+                w.write_all(&self.to_le_bytes())
+            }
+
+            fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+                let mut bytes = [0; std::mem::size_of::<$t>()];
+                r.read_exact(&mut bytes)?;
+                Ok(<$t>::from_le_bytes(bytes))
+            }
+        })*
+    }
+}
+
+codec_num!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl Codec for usize {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u64).encode(w)
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        match usize::try_from(u64::decode(r)?) {
+            Ok(n) => Ok(n),
+            Err(_) => invalid("length out of range")
+        }
+    }
+}
+
+impl Codec for isize {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (*self as i64).encode(w)
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        match isize::try_from(i64::decode(r)?) {
+            Ok(n) => Ok(n),
+            Err(_) => invalid("integer out of range")
+        }
+    }
+}
+
+impl Codec for bool {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u8).encode(w)
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        match u8::decode(r)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => invalid("invalid bool")
+        }
+    }
+}
+
+impl Codec for char {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u32).encode(w)
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        match char::from_u32(u32::decode(r)?) {
+            Some(c) => Ok(c),
+            None => invalid("invalid char")
+        }
+    }
+}
+
+impl Codec for String {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.len().encode(w)?;
+        w.write_all(self.as_bytes())
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        match String::from_utf8(read_bytes(r)?) {
+            Ok(s) => Ok(s),
+            Err(_) => invalid("invalid utf-8 string")
+        }
+    }
+}
+
+// Reads a length prefixed byte string without trusting the length for the
+// size of the buffer, so a corrupted length can't exhaust the memory.
+fn read_bytes<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let len = u64::decode(r)?;
+    let mut bytes = Vec::new();
+    if r.take(len).read_to_end(&mut bytes)? as u64 != len {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+impl<T: Codec> Codec for Vec<T> {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.len().encode(w)?;
+        self.iter().try_for_each(|item| item.encode(w))
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let len = usize::decode(r)?;
+        let mut items = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            items.push(T::decode(r)?);
+        }
+        Ok(items)
+    }
+}
+
+impl Codec for () {
+    fn encode<W: Write>(&self, _: &mut W) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn decode<R: Read>(_: &mut R) -> io::Result<Self> {
+        Ok(())
+    }
+}
+
+impl<A: Codec, B: Codec> Codec for (A, B) {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.0.encode(w)?;
+        self.1.encode(w)
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok((A::decode(r)?, B::decode(r)?))
+    }
+}
+
+impl<A: Codec, B: Codec, C: Codec> Codec for (A, B, C) {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.0.encode(w)?;
+        self.1.encode(w)?;
+        self.2.encode(w)
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok((A::decode(r)?, B::decode(r)?, C::decode(r)?))
+    }
+}
+
+const MAGIC: [u8; 8] = *b"RBTSNAP\0";
+const VERSION: u32 = 1;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// Computes the FNV-1a hash of all the bytes passing through.
+struct Checksum<T> {
+    inner: T,
+    hash: u64
+}
+
+impl<T> Checksum<T> {
+    fn new(inner: T) -> Self {
+        Checksum { inner, hash: FNV_OFFSET }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.hash = (self.hash ^ *b as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+impl<W: Write> Write for Checksum<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Checksum<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<K: Key + Codec, V: Value + Codec, A: NodeAlloc<KeyValue<K, V>>> RBTree<KeyValue<K, V>, A> {
+    /// Writes all the entries of the tree in key order, after a header with
+    /// a magic number, the format version and the number of entries, and
+    /// followed by a checksum. Every field is written separately, so `w`
+    /// should be buffered.
+    pub fn write_snapshot<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut w = Checksum::new(w);
+        w.write_all(&MAGIC)?;
+        VERSION.encode(&mut w)?;
+        (self.size as u64).encode(&mut w)?;
+        // A cursor keeps only the path to the current node, so writing takes
+        // O(log n) memory rather than a copy of every pointer.
+        let mut cursor = self.cursor_front();
+        while let Some(node) = cursor.current() {
+            node.key().encode(&mut w)?;
+            node.value().encode(&mut w)?;
+            cursor.move_next();
+        }
+        let hash = w.hash;
+        hash.encode(&mut w.inner)
+    }
+
+    /// Reads a tree written by `write_snapshot`. The tree is linked in
+    /// linear time, as the entries are already sorted.
+    pub fn read_snapshot<R: Read>(r: &mut R) -> io::Result<Self> where A: Default {
+        let mut tree = RBTree::default();
+        // The nodes read so far are linked even on failure, so that they are
+        // released with the tree.
+        Self::read_entries(&mut Sorted::new(&mut tree), r)?;
+        Ok(tree)
+    }
+
+    fn read_entries<R: Read>(sorted: &mut Sorted<KeyValue<K, V>, A>, r: &mut R) -> io::Result<()> {
+        let mut r = Checksum::new(r);
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return invalid("not a tree snapshot");
+        }
+        if u32::decode(&mut r)? != VERSION {
+            return invalid("unsupported snapshot version");
+        }
+        let count = u64::decode(&mut r)?;
+        for _ in 0..count {
+            let node = KeyValue::new(K::decode(&mut r)?, V::decode(&mut r)?);
+            if let Some(last) = sorted.last() {
+                if last.key() >= node.key() {
+                    return invalid("snapshot entries are not sorted");
+                }
+            }
+            sorted.push(node);
+        }
+        let hash = r.hash;
+        if u64::decode(&mut r.inner)? != hash {
+            return invalid("snapshot checksum mismatch");
+        }
+        Ok(())
+    }
+}
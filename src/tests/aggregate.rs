@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result};
+use std::ops::RangeBounds;
+
+use rand::Rng;
+
+use crate::{AggregateNode, Count, Max, Min, Monoid, Node, PtrExt, RBTree, Sum};
+
+use super::range;
+
+impl<M: Monoid + for<'a> From<&'a i64>> Display for AggregateNode<i32, i64, M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_fmt(format_args!("{}:{}", if self.is_black() { 'B' } else { 'R' }, self.key()))
+    }
+}
+
+#[test]
+fn test_aggregate_sum() {
+    let mut rng = rand::thread_rng();
+    let mut tree: RBTree<AggregateNode<i32, i64, Sum<i64>>> = RBTree::new();
+    let mut expected = BTreeMap::new();
+    let count = if cfg!(miri) { 100 } else { 2000 };
+    for _ in 0..count * 2 {
+        let (key, value) = (rng.gen_range(0, count), rng.gen_range(-100, 100));
+        if rng.gen_ratio(1, 4) {
+            assert_eq!(expected.remove(&key).is_some(), tree.delete(&key));
+        } else {
+            // replacing the value of a key refreshes the sums above it
+            tree.insert_owned(AggregateNode::new(key, value));
+            expected.insert(key, value);
+        }
+    }
+    tree.validate();
+    for _ in 0..200 {
+        let range = range(&mut rng, count);
+        let sum: i64 = expected.iter().filter(|(k, _)| range.contains(*k)).map(|(_, v)| v).sum();
+        assert_eq!(Sum(sum), tree.aggregate(range), "{:?}", range);
+    }
+    assert_eq!(Sum(expected.values().sum()), tree.aggregate(..));
+    assert_eq!(Sum(0), tree.aggregate(count..));
+}
+
+#[test]
+fn test_aggregate_min_max_count() {
+    let mut min: RBTree<AggregateNode<i32, i64, Min<i64>>> = RBTree::new();
+    let mut max: RBTree<AggregateNode<i32, i64, Max<i64>>> = RBTree::new();
+    let mut count: RBTree<AggregateNode<i32, i64, Count>> = RBTree::new();
+    for k in 0..100 {
+        let value = (k as i64 * 37) % 101;
+        min.insert_owned(AggregateNode::new(k, value));
+        max.insert_owned(AggregateNode::new(k, value));
+        count.insert_owned(AggregateNode::new(k, value));
+    }
+    for k in 0..50 {
+        min.delete(&(k * 2));
+        max.delete(&(k * 2));
+        count.delete(&(k * 2));
+    }
+    min.validate();
+    for (start, end) in [(0, 100), (10, 20), (33, 34), (34, 35), (60, 99)] {
+        let values = || (start..end).filter(|k| k % 2 == 1).map(|k| (k as i64 * 37) % 101);
+        assert_eq!(Min(values().min()), min.aggregate(start..end));
+        assert_eq!(Max(values().max()), max.aggregate(start..end));
+        assert_eq!(Count(values().count()), count.aggregate(start..end));
+    }
+    // the root sums up the whole tree
+    assert_eq!(Count(50), *count.root.node(&count.alloc).summary());
+}
+
+#[test]
+fn test_select_and_quantile() {
+    let mut rng = rand::thread_rng();
+    let mut tree: RBTree<AggregateNode<i32, i64, Count>> = RBTree::new();
+    assert!(tree.median().is_none());
+    let mut expected = BTreeMap::new();
+    let count = if cfg!(miri) { 100 } else { 2000 };
+    for _ in 0..count {
+        let key = rng.gen_range(0, count * 10);
+        tree.insert_owned(AggregateNode::new(key, 0));
+        expected.insert(key, 0);
+    }
+    let keys: Vec<i32> = expected.keys().copied().collect();
+    for (rank, key) in keys.iter().enumerate() {
+        assert_eq!(key, tree.select(rank).unwrap().key());
+    }
+    assert!(tree.select(keys.len()).is_none());
+
+    let n = keys.len();
+    assert_eq!(keys[0], *tree.quantile(0.0).unwrap().key());
+    assert_eq!(keys[n - 1], *tree.quantile(1.0).unwrap().key());
+    assert_eq!(keys[(n - 1) / 2], *tree.median().unwrap().key());
+    for q in [0.01, 0.25, 0.5, 0.9, 0.99] {
+        let rank = (q * n as f64).ceil() as usize - 1;
+        assert_eq!(keys[rank], *tree.quantile(q).unwrap().key(), "{}", q);
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_quantile_out_of_range() {
+    let tree: RBTree<AggregateNode<i32, i64, Count>> = RBTree::new();
+    tree.quantile(1.5);
+}
@@ -0,0 +1,91 @@
+use std::mem::size_of;
+
+use rand::seq::SliceRandom;
+
+use crate::{Arena, Counting, RBTree, StaticNode, StaticRBTree};
+
+use super::KV32;
+
+#[test]
+fn test_arena() {
+    let mut rng = rand::thread_rng();
+    let mut tree: RBTree<KV32, Arena<KV32>> = RBTree::with_alloc(Arena::new());
+    let mut keys: Vec<i32> = (0..1000).collect();
+
+    keys.shuffle(&mut rng);
+    for k in keys.iter() {
+        assert!(tree.insert(&KV32::same(*k)));
+    }
+    tree.validate();
+    let capacity = tree.alloc().capacity();
+    assert!((1000..2000).contains(&capacity));
+
+    // released slots are reused before the arena grows
+    keys.shuffle(&mut rng);
+    for k in keys[..500].iter() {
+        assert!(tree.delete(k));
+    }
+    for k in keys[..500].iter() {
+        assert!(tree.insert(&KV32::same(*k)));
+    }
+    tree.validate();
+    assert_eq!(capacity, tree.alloc().capacity());
+    for k in keys.iter() {
+        assert_eq!(k, tree.search(k).unwrap().value());
+    }
+}
+
+#[test]
+fn test_counting() {
+    let mut tree: RBTree<KV32, Counting<Arena<KV32>>> = RBTree::default();
+    for k in 0..100 {
+        tree.insert(&KV32::same(k));
+    }
+    tree.insert(&KV32::new(0, 1));
+    assert_eq!(100 * size_of::<KV32>(), tree.alloc().bytes());
+
+    tree.retain(|node| node.value() % 2 == 0);
+    assert_eq!(49 * size_of::<KV32>(), tree.alloc().bytes());
+    assert_eq!(100 * size_of::<KV32>(), tree.alloc().peak_bytes());
+    assert!(tree.alloc().inner().capacity() >= 100);
+}
+
+#[test]
+fn test_try_insert() {
+    let mut tree: StaticRBTree<i32, i32, 100, u8> = StaticRBTree::default();
+    let mut keys: Vec<i32> = (0..200).collect();
+    keys.shuffle(&mut rand::thread_rng());
+    for k in keys[..100].iter() {
+        assert!(tree.try_insert(StaticNode::new(*k, *k)).unwrap().is_none());
+    }
+
+    // a full allocator hands the node back and leaves the tree alone
+    for k in keys[100..].iter() {
+        match tree.try_insert(StaticNode::new(*k, -1)) {
+            Err(err) => assert_eq!(-1, *err.into_node().value()),
+            Ok(_) => panic!("inserted {} into a full tree", k)
+        }
+        assert!(tree.search(k).is_none());
+    }
+    tree.validate();
+    assert_eq!(100, tree.size());
+
+    // replacing needs no allocation
+    let replaced = tree.try_insert(StaticNode::new(keys[0], -1)).unwrap().unwrap();
+    assert_eq!(keys[0], *replaced.value());
+    assert!(tree.delete(&keys[1]));
+    assert!(tree.try_insert(StaticNode::new(keys[100], 0)).unwrap().is_none());
+    tree.validate();
+
+    let mut tree: RBTree<KV32, Counting<Arena<KV32>>> = RBTree::default();
+    for k in keys.iter() {
+        assert!(tree.try_insert(KV32::same(*k)).unwrap().is_none());
+    }
+    assert_eq!(200 * size_of::<KV32>(), tree.alloc().bytes());
+
+    let mut tree: RBTree<KV32> = RBTree::default();
+    for k in keys.iter() {
+        assert!(tree.try_insert(KV32::same(*k)).unwrap().is_none());
+    }
+    tree.validate();
+}
@@ -0,0 +1,166 @@
+use rand::seq::SliceRandom;
+
+use crate::{Node, RBTree};
+
+use super::KV32;
+
+fn tree_of(keys: &[i32]) -> RBTree<KV32> {
+    let mut tree: RBTree<KV32> = RBTree::new();
+    for k in keys {
+        tree.insert(&KV32::same(*k));
+    }
+    tree
+}
+
+#[test]
+fn test_cursor_move() {
+    let tree = tree_of(&[64, 32, 96, 16, 48, 80, 112]);
+
+    let mut cursor = tree.cursor_front();
+    let mut keys = vec![];
+    while let Some(node) = cursor.current() {
+        keys.push(*node.key());
+        cursor.move_next();
+    }
+    assert_eq!(vec![16, 32, 48, 64, 80, 96, 112], keys);
+
+    // moving on from the ghost position wraps around
+    cursor.move_next();
+    assert_eq!(16, *cursor.current().unwrap().key());
+    cursor.move_prev();
+    assert!(cursor.current().is_none());
+    cursor.move_prev();
+    assert_eq!(112, *cursor.current().unwrap().key());
+
+    let mut cursor = tree.cursor_back();
+    let mut keys = vec![];
+    while let Some(node) = cursor.current() {
+        keys.push(*node.key());
+        cursor.move_prev();
+    }
+    assert_eq!(vec![112, 96, 80, 64, 48, 32, 16], keys);
+}
+
+#[test]
+fn test_cursor_at() {
+    let tree = tree_of(&[64, 32, 96, 16, 48, 80, 112]);
+    assert_eq!(48, *tree.cursor_at(&48).current().unwrap().key());
+    assert_eq!(64, *tree.cursor_at(&49).current().unwrap().key());
+    assert_eq!(16, *tree.cursor_at(&0).current().unwrap().key());
+    assert!(tree.cursor_at(&113).current().is_none());
+
+    let mut cursor = tree.cursor_at(&70);
+    cursor.move_prev();
+    assert_eq!(64, *cursor.current().unwrap().key());
+
+    let empty: RBTree<KV32> = RBTree::new();
+    assert!(empty.cursor_front().current().is_none());
+    assert!(empty.cursor_back().current().is_none());
+}
+
+#[test]
+fn test_cursor_remove_current() {
+    let mut tree = tree_of(&(0..64).collect::<Vec<i32>>());
+    let mut cursor = tree.cursor_front_mut();
+    while let Some(node) = cursor.current() {
+        if node.key() % 3 == 0 {
+            assert!(cursor.remove_current().is_some());
+        } else {
+            cursor.move_next();
+        }
+    }
+    assert!(cursor.remove_current().is_none());
+    tree.validate();
+    assert_eq!(42, tree.size());
+    for k in 0..64 {
+        assert_eq!(k % 3 != 0, tree.search(&k).is_some());
+    }
+}
+
+#[test]
+fn test_cursor_insert_after() {
+    let mut tree = tree_of(&[32, 64]);
+    let mut cursor = tree.cursor_at_mut(&32);
+    assert_eq!(64, *cursor.insert_after(KV32::same(64)).unwrap_err().into_node().key());
+    assert_eq!(16, *cursor.insert_after(KV32::same(16)).unwrap_err().into_node().key());
+    assert!(cursor.insert_after(KV32::same(48)).is_ok());
+    assert!(cursor.insert_after(KV32::same(40)).is_ok());
+    assert_eq!(32, *cursor.current().unwrap().key());
+    cursor.move_next();
+    assert_eq!(40, *cursor.current().unwrap().key());
+
+    // at the ghost position nodes are inserted at the front
+    let mut cursor = tree.cursor_back_mut();
+    cursor.move_next();
+    assert!(cursor.insert_after(KV32::same(96)).is_err());
+    assert!(cursor.insert_after(KV32::same(16)).is_ok());
+    assert!(cursor.current().is_none());
+    assert_eq!("RBTree{size:5,tree:(((R:16),B:32,(R:40)),B:48,(B:64))}", tree.to_string());
+    tree.validate();
+}
+
+#[test]
+fn test_cursor_insert_after_random() {
+    let mut rng = rand::thread_rng();
+    let mut keys: Vec<i32> = (0..if cfg!(miri) { 64 } else { 512 }).map(|k| k * 4).collect();
+    keys.shuffle(&mut rng);
+    let mut tree = tree_of(&keys);
+
+    // fills the gaps after every key, staying on it through the rotations
+    for k in keys.iter() {
+        let mut cursor = tree.cursor_at_mut(k);
+        for gap in (1..4).rev() {
+            assert!(cursor.insert_after(KV32::same(k + gap)).is_ok());
+            assert_eq!(*k, *cursor.current().unwrap().key());
+        }
+        tree.validate();
+    }
+    let mut cursor = tree.cursor_front();
+    for k in 0..keys.len() as i32 * 4 {
+        assert_eq!(k, *cursor.current().unwrap().key());
+        cursor.move_next();
+    }
+    assert!(cursor.current().is_none());
+}
+
+#[test]
+fn test_cursor_random_remove() {
+    let mut rng = rand::thread_rng();
+    let mut keys: Vec<i32> = (0..if cfg!(miri) { 127 } else { 1023 }).collect();
+    keys.shuffle(&mut rng);
+    let mut tree = tree_of(&keys);
+
+    keys.shuffle(&mut rng);
+    for k in keys.iter() {
+        let mut cursor = tree.cursor_at_mut(k);
+        assert!(cursor.remove_current().is_some());
+        let next = cursor.current().map(|node| *node.key());
+        assert_eq!(next, tree.cursor_at(k).current().map(|node| *node.key()));
+        tree.validate();
+    }
+    assert_eq!(0, tree.size());
+}
+
+// The cursor finds its way down to the next node again by the nodes it
+// passed, wherever the rotations of the removal move them.
+#[test]
+fn test_cursor_remove_anywhere() {
+    let mut rng = rand::thread_rng();
+    let count = if cfg!(miri) { 50 } else { 500 };
+    let mut keys: Vec<i32> = (0..count).collect();
+    let mut tree = tree_of(&keys);
+    let mut order = keys.clone();
+    order.shuffle(&mut rng);
+    for k in order {
+        let mut cursor = tree.cursor_at_mut(&k);
+        assert_eq!(k, *cursor.remove_current().unwrap().key());
+        keys.retain(|key| *key != k);
+        let next = keys.iter().find(|key| **key > k);
+        assert_eq!(next, cursor.current().map(|node| node.key()));
+        cursor.move_prev();
+        let prev = keys.iter().rev().find(|key| **key < k);
+        assert_eq!(prev, cursor.current().map(|node| node.key()));
+    }
+    tree.validate();
+    assert_eq!(0, tree.size());
+}
@@ -8,7 +8,7 @@ use crate::kv::Color::{BLACK, RED};
 fn test_delete_1() {
     let mut tree: RBTree<KV32> = RBTree::new();
     tree.insert(&KV32::same(64));
-    assert_eq!(false, tree.delete(&32));
+    assert!(!tree.delete(&32));
     assert_eq!("RBTree{size:1,tree:(B:64)}", tree.to_string());
 }
 
@@ -27,7 +27,7 @@ fn test_delete_2() {
     tree.insert(&KV32::same(96));
     assert_eq!("RBTree{size:3,tree:((R:32),B:64,(R:96))}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&32));
+    assert!(tree.delete(&32));
     tree.validate();
     assert_eq!("RBTree{size:2,tree:(B:64,(R:96))}", tree.to_string());
 }
@@ -48,7 +48,7 @@ fn test_delete_3() {
     tree.insert_at(32, 48, RED);
     assert_eq!("RBTree{size:4,tree:((B:32,(R:48)),B:64,(B:96))}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&32));
+    assert!(tree.delete(&32));
     assert_eq!("RBTree{size:3,tree:((B:48),B:64,(B:96))}", tree.to_string());
     tree.validate();
 }
@@ -63,7 +63,7 @@ fn test_delete_4() {
     tree.insert(&KV32::same(64));
     assert_eq!("RBTree{size:1,tree:(B:64)}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&64));
+    assert!(tree.delete(&64));
     tree.validate();
     assert_eq!("RBTree{size:0}", tree.to_string());
 }
@@ -85,7 +85,7 @@ fn test_delete_5_left() {
     tree.insert_at(96, 112, BLACK);
     assert_eq!("RBTree{size:5,tree:((B:32),B:64,((B:80),R:96,(B:112)))}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&32));
+    assert!(tree.delete(&32));
     assert_eq!("RBTree{size:4,tree:((B:64,(R:80)),B:96,(B:112))}", tree.to_string());
     tree.validate();
 }
@@ -107,7 +107,7 @@ fn test_delete_5_right() {
     tree.insert_at(32, 16, BLACK);
     assert_eq!("RBTree{size:5,tree:(((B:16),R:32,(B:48)),B:64,(B:96))}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&96));
+    assert!(tree.delete(&96));
     assert_eq!("RBTree{size:4,tree:((B:16),B:32,((R:48),B:64))}", tree.to_string());
     tree.validate();
 }
@@ -127,7 +127,7 @@ fn test_delete_6() {
     tree.insert_at(64, 96, BLACK);
     assert_eq!("RBTree{size:3,tree:((B:32),B:64,(B:96))}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&32));
+    assert!(tree.delete(&32));
     assert_eq!("RBTree{size:2,tree:(B:64,(R:96))}", tree.to_string());
     tree.validate();
 }
@@ -153,7 +153,7 @@ fn test_delete_7() {
     tree.insert_at(96, 112, BLACK);
     assert_eq!("RBTree{size:7,tree:(((B:16),B:32,(B:48)),B:64,((B:80),B:96,(B:112)))}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&16));
+    assert!(tree.delete(&16));
     assert_eq!("RBTree{size:6,tree:((B:32,(R:48)),B:64,((B:80),R:96,(B:112)))}", tree.to_string());
     tree.validate();
 }
@@ -174,7 +174,7 @@ fn test_delete_8_left() {
     tree.insert_at(96, 112, RED);
     assert_eq!("RBTree{size:4,tree:((B:32),B:64,(B:96,(R:112)))}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&32));
+    assert!(tree.delete(&32));
     assert_eq!("RBTree{size:3,tree:((B:64),B:96,(B:112))}", tree.to_string());
     tree.validate();
 }
@@ -195,7 +195,7 @@ fn test_delete_8_right() {
     tree.insert_at(64, 96, BLACK);
     assert_eq!("RBTree{size:4,tree:(((R:16),B:32),B:64,(B:96))}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&96));
+    assert!(tree.delete(&96));
     assert_eq!("RBTree{size:3,tree:((B:16),B:32,(B:64))}", tree.to_string());
     tree.validate();
 }
@@ -216,7 +216,7 @@ fn test_delete_9_left() {
     tree.insert_at(96, 80, RED);
     assert_eq!("RBTree{size:4,tree:((B:32),B:64,((R:80),B:96))}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&32));
+    assert!(tree.delete(&32));
     assert_eq!("RBTree{size:3,tree:((B:64),B:80,(B:96))}", tree.to_string());
     tree.validate();
 }
@@ -237,7 +237,7 @@ fn test_delete_9_right() {
     tree.insert_at(32, 48, RED);
     assert_eq!("RBTree{size:4,tree:((B:32,(R:48)),B:64,(B:96))}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&96));
+    assert!(tree.delete(&96));
     assert_eq!("RBTree{size:3,tree:((B:32),B:48,(B:64))}", tree.to_string());
     tree.validate();
 }
@@ -259,7 +259,7 @@ fn test_delete_10() {
     tree.insert_at(96, 112, BLACK);
     assert_eq!("RBTree{size:5,tree:((B:32),B:64,((B:80),R:96,(B:112)))}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&64));
+    assert!(tree.delete(&64));
     assert_eq!("RBTree{size:4,tree:((B:32),B:80,(B:96,(R:112)))}", tree.to_string());
     tree.validate();
 }
@@ -282,7 +282,7 @@ fn test_delete_11() {
     tree.insert_at(32, 48, RED);
     assert_eq!("RBTree{size:5,tree:(((R:16),B:32,(R:48)),B:64,(B:96))}", tree.to_string());
     tree.validate();
-    assert_eq!(true, tree.delete(&64));
+    assert!(tree.delete(&64));
     assert_eq!("RBTree{size:4,tree:((B:16),B:32,((R:48),B:96))}", tree.to_string());
     tree.validate();
 }
@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result};
+
+use rand::Rng;
+
+use crate::{HandleEntry, HandleMap, Heap, Node};
+
+impl Display for HandleEntry<i32, u32> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_fmt(format_args!("{}:{}", if self.is_black() { 'B' } else { 'R' }, self.key()))
+    }
+}
+
+#[test]
+fn test_handles() {
+    let mut rng = rand::thread_rng();
+    let mut map = HandleMap::new();
+    let mut expected = BTreeMap::new();
+    let mut stale = Vec::new();
+    let count = if cfg!(miri) { 100 } else { 3000 };
+    for _ in 0..count {
+        let key = rng.gen_range(0, count / 4);
+        match rng.gen_range(0, 4) {
+            0 => {
+                let handle = map.handle(&key);
+                assert_eq!(expected.remove(&key).map(|(_, value)| (key, value)), handle.and_then(|h| map.remove_by_handle(h)));
+                stale.extend(handle);
+            }
+            1 => {
+                let handle = map.handle(&key);
+                assert_eq!(expected.remove(&key).map(|(_, value)| value), map.remove(&key));
+                stale.extend(handle);
+            }
+            _ => {
+                let value = rng.gen::<u32>();
+                let handle = map.insert_handle(key, value);
+                if let Some((replaced, _)) = expected.insert(key, (handle, value)) {
+                    stale.push(replaced);
+                }
+            }
+        }
+    }
+    map.tree.validate();
+    assert_eq!(expected.len(), map.len());
+    for (key, (handle, value)) in &expected {
+        assert_eq!(Some(handle), map.handle(key).as_ref());
+        assert_eq!(Some(key), map.key(*handle));
+        assert_eq!(Some(value), map.get(*handle));
+        *map.get_mut(*handle).unwrap() += 1;
+        assert_eq!(Some(&(value + 1)), map.search(key));
+    }
+    // slots are reused, but not by the stale handles
+    for handle in stale {
+        assert_eq!(None, map.get(handle));
+        assert_eq!(None, map.remove_by_handle(handle));
+    }
+    for (key, (handle, value)) in expected {
+        assert_eq!(Some((key, value + 1)), map.remove_by_handle(handle));
+    }
+    assert!(map.is_empty());
+}
+
+#[test]
+fn test_handle_replaced() {
+    let mut map = HandleMap::new();
+    let first = map.insert_handle("a", 1);
+    let other = map.insert_handle("b", 2);
+    let second = map.insert_handle("a", 3);
+    assert_ne!(first, second);
+    assert_eq!(None, map.get(first));
+    assert_eq!(Some(&3), map.get(second));
+    assert_eq!(2, map.len());
+    assert_eq!(Some(("b", 2)), map.remove_by_handle(other));
+    assert_eq!(Some(("a", 3)), map.remove_by_handle(second));
+    let third = map.insert_handle("c", 4);
+    assert_eq!(None, map.get(second));
+    assert_eq!(Some(&"c"), map.key(third));
+}
+
+#[test]
+fn test_retired_slot() {
+    let mut map = HandleMap::with_last_generation(Heap, 3);
+    let mut stale = Vec::new();
+    // the same slot is handed out again after each removal, for more times
+    // than it has generations
+    for k in 0..10 {
+        let handle = map.insert_handle(k, k);
+        assert!(!stale.contains(&handle));
+        assert_eq!(Some(&k), map.get(handle));
+        if k % 2 == 0 {
+            assert_eq!(Some(k), map.remove(&k));
+        } else {
+            assert_eq!(Some((k, k)), map.remove_by_handle(handle));
+        }
+        stale.push(handle);
+    }
+    let handle = map.insert_handle(10, 10);
+    assert!(!stale.contains(&handle));
+    for handle in stale {
+        assert_eq!(None, map.get(handle));
+        assert_eq!(None, map.remove_by_handle(handle));
+    }
+    assert_eq!(Some(&10), map.get(handle));
+}
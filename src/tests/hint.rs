@@ -0,0 +1,210 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+
+use rand::Rng;
+
+use crate::{Heap, KeyValue, Node, NodeAlloc, PtrExt, RBTree};
+
+use super::KV32;
+
+thread_local! {
+    static COMPARISONS: Cell<usize> = const { Cell::new(0) };
+    static LOADS: Cell<usize> = const { Cell::new(0) };
+}
+
+// A key which counts how often it is compared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Counted(i64);
+
+impl PartialOrd for Counted {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Counted {
+    fn cmp(&self, other: &Self) -> Ordering {
+        COMPARISONS.with(|c| c.set(c.get() + 1));
+        self.0.cmp(&other.0)
+    }
+}
+
+impl Display for KeyValue<Counted, ()> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("{}:{}", if self.is_black() { 'B' } else { 'R' }, self.key().0))
+    }
+}
+
+fn comparisons() -> usize {
+    COMPARISONS.with(|c| c.replace(0))
+}
+
+// The key of the node at the end of the cached route.
+fn last_key(tree: &RBTree<KV32>) -> i32 {
+    let mut last = None;
+    tree.follow(tree.last_route(), |ptr| last = Some(ptr));
+    *last.unwrap().node(&tree.alloc).key()
+}
+
+#[test]
+fn test_sequential_insert() {
+    let count = if cfg!(miri) { 100 } else { 10000 };
+    let mut tree: RBTree<KeyValue<Counted, ()>> = RBTree::new();
+    comparisons();
+    for k in 0..count {
+        tree.insert_owned(KeyValue::new(Counted(k), ()));
+    }
+    // a single comparison with the last node, which has no successor
+    assert_eq!(count as usize - 1, comparisons());
+
+    for k in (-count..0).rev() {
+        tree.insert_owned(KeyValue::new(Counted(k), ()));
+    }
+    assert!(comparisons() < 2 * count as usize);
+    tree.validate();
+    assert_eq!(2 * count as usize, tree.size());
+
+    // replacing the last node takes a single comparison as well
+    comparisons();
+    tree.insert_owned(KeyValue::new(Counted(-count), ()));
+    assert_eq!(1, comparisons());
+}
+
+// The heap, counting how often nodes are dereferenced.
+#[derive(Default)]
+struct Loads(Heap);
+
+unsafe impl NodeAlloc<KV32> for Loads {
+    fn alloc(&mut self, node: KV32) -> <KV32 as Node>::Ptr {
+        self.0.alloc(node)
+    }
+
+    unsafe fn free(&mut self, ptr: <KV32 as Node>::Ptr) -> KV32 {
+        self.0.free(ptr)
+    }
+
+    unsafe fn node(&self, ptr: <KV32 as Node>::Ptr) -> &KV32 {
+        LOADS.with(|c| c.set(c.get() + 1));
+        self.0.node(ptr)
+    }
+
+    unsafe fn node_mut(&self, ptr: <KV32 as Node>::Ptr) -> &mut KV32 {
+        LOADS.with(|c| c.set(c.get() + 1));
+        self.0.node_mut(ptr)
+    }
+}
+
+fn loads() -> usize {
+    LOADS.with(|c| c.replace(0))
+}
+
+// Appending takes as many loads per node into a large tree as into a small
+// one, rather than one more per level.
+#[test]
+fn test_append_cost() {
+    let (small, large) = if cfg!(miri) { (1 << 6, 1 << 10) } else { (1 << 10, 1 << 16) };
+    let mut tree: RBTree<KV32, Loads> = RBTree::default();
+    let appended = |tree: &mut RBTree<KV32, Loads>, from: i32, to: i32| {
+        loads();
+        for k in from..to {
+            tree.insert_owned(KV32::same(k));
+        }
+        loads() / (to - from) as usize
+    };
+    appended(&mut tree, 0, small);
+    let small_cost = appended(&mut tree, small, 2 * small);
+    appended(&mut tree, 2 * small, large);
+    let large_cost = appended(&mut tree, large, 2 * large);
+    assert!(large_cost <= small_cost, "{} loads per append at {}, {} at {}", small_cost, small, large_cost, large);
+    tree.validate();
+    assert_eq!(2 * large as usize, tree.size());
+}
+
+#[test]
+fn test_nearly_sorted_insert() {
+    let mut rng = rand::thread_rng();
+    let mut tree: RBTree<KV32> = RBTree::new();
+    let count = if cfg!(miri) { 200 } else { 5000 };
+    for k in 0..count {
+        tree.insert(&KV32::same(k));
+        assert_eq!(k, last_key(&tree));
+        if rng.gen_ratio(1, 10) {
+            let key = rng.gen_range(0, count);
+            tree.insert(&KV32::same(key));
+            assert_eq!(key, last_key(&tree));
+        }
+    }
+    tree.validate();
+    for k in 0..count {
+        assert_eq!(k, *tree.search(&k).unwrap().value());
+    }
+}
+
+// The neighbours kept for the hint are dropped once a node is removed, as
+// it may have been one of them.
+#[test]
+fn test_insert_after_remove() {
+    let mut tree: RBTree<KV32> = RBTree::new();
+    for k in (0..100).map(|k| k * 2) {
+        tree.insert(&KV32::same(k));
+    }
+    tree.insert(&KV32::same(51));
+    assert!(tree.delete(&52));
+    assert!(tree.delete(&50));
+    for k in [53, 50, 52, 49] {
+        tree.insert(&KV32::same(k));
+        assert_eq!(k, last_key(&tree));
+    }
+    tree.validate();
+    assert_eq!(103, tree.size());
+}
+
+#[test]
+fn test_insert_with_hint() {
+    let mut tree: RBTree<KV32> = RBTree::new();
+    for k in (0..100).map(|k| k * 10) {
+        tree.insert(&KV32::same(k));
+    }
+    let mut cursor = tree.cursor_at_mut(&500);
+    for k in 501..510 {
+        assert!(cursor.insert_with_hint(KV32::same(k)).is_none());
+        assert_eq!(k, *cursor.current().unwrap().key());
+    }
+    // far from the cursor
+    assert!(cursor.insert_with_hint(KV32::same(5)).is_none());
+    assert_eq!(5, *cursor.current().unwrap().key());
+    cursor.move_next();
+    assert_eq!(10, *cursor.current().unwrap().key());
+    assert_eq!(10, *cursor.insert_with_hint(KV32::new(10, 0)).unwrap().value());
+    assert_eq!(0, *cursor.current().unwrap().value());
+
+    tree.validate();
+    assert_eq!(110, tree.size());
+}
+
+#[test]
+fn test_tree_insert_with_hint() {
+    let mut tree: RBTree<KeyValue<Counted, ()>> = RBTree::new();
+    for k in (0..100).map(|k| k * 10) {
+        tree.insert_owned(KeyValue::new(Counted(k), ()));
+    }
+    let hint = tree.cursor_at(&Counted(500)).hint();
+    comparisons();
+    assert!(tree.insert_with_hint(hint, KeyValue::new(Counted(505), ())).is_none());
+    assert_eq!(2, comparisons());
+
+    // the hint to the last node appended, which has no successor
+    tree.insert_owned(KeyValue::new(Counted(1000), ()));
+    let hint = tree.last_hint();
+    comparisons();
+    assert!(tree.insert_with_hint(hint, KeyValue::new(Counted(1005), ())).is_none());
+    assert_eq!(1, comparisons());
+
+    // a stale hint leads elsewhere, and the key is searched for
+    assert!(tree.delete(&Counted(10)));
+    assert!(tree.insert_with_hint(hint, KeyValue::new(Counted(15), ())).is_none());
+    tree.validate();
+    assert_eq!(103, tree.size());
+    assert!(tree.search(&Counted(15)).is_some());
+}
@@ -0,0 +1,84 @@
+use std::fmt::{Display, Formatter, Result};
+use std::mem::size_of;
+use std::rc::Rc;
+
+use rand::seq::SliceRandom;
+
+use crate::{Inline, Node, SlotIndex, StaticNode, StaticRBTree};
+
+impl<K: Ord + std::fmt::Debug + Display, V, I: SlotIndex> Display for StaticNode<K, V, I> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_fmt(format_args!("{}:{}", if self.is_black() { 'B' } else { 'R' }, self.key()))
+    }
+}
+
+#[test]
+fn test_static_tree() {
+    let mut rng = rand::thread_rng();
+    let mut tree: StaticRBTree<i32, i32, 200, u8> = StaticRBTree::default();
+    let mut keys: Vec<i32> = (0..200).collect();
+    for _ in 0..3 {
+        keys.shuffle(&mut rng);
+        for k in keys.iter() {
+            assert!(tree.insert_owned(StaticNode::new(*k, *k * 2)).is_none());
+        }
+        tree.validate();
+        keys.shuffle(&mut rng);
+        for k in keys[..150].iter() {
+            assert_eq!(*k * 2, *tree.remove(k).unwrap().value());
+        }
+        tree.validate();
+        for k in keys[..150].iter() {
+            assert!(tree.insert_owned(StaticNode::new(*k, *k * 2)).is_none());
+        }
+        for k in keys.iter() {
+            assert!(tree.delete(k));
+        }
+        assert_eq!(0, tree.size());
+    }
+    assert_eq!(200, tree.alloc().capacity());
+}
+
+#[test]
+fn test_static_node_size() {
+    assert_eq!(5, size_of::<StaticNode<u8, u8, u8>>());
+    assert_eq!(10, size_of::<StaticNode<u16, u16>>());
+}
+
+#[test]
+fn test_static_tree_moved() {
+    let mut tree: StaticRBTree<u32, u32, 64> = StaticRBTree::with_alloc(Inline::new());
+    for k in 0..64 {
+        tree.insert_owned(StaticNode::new(k, k));
+    }
+    let moved = Box::new(tree);
+    moved.validate();
+    for k in 0..64 {
+        assert_eq!(k, *moved.search(&k).unwrap().value());
+    }
+}
+
+#[test]
+#[should_panic(expected = "inline allocator is full")]
+fn test_static_tree_full() {
+    let mut tree: StaticRBTree<u32, (), 4, u8> = StaticRBTree::default();
+    for k in 0..5 {
+        tree.insert_owned(StaticNode::new(k, ()));
+    }
+}
+
+#[test]
+fn test_static_tree_drop() {
+    let value = Rc::new(());
+    {
+        let mut tree: StaticRBTree<u32, Rc<()>, 100> = StaticRBTree::default();
+        for k in 0..100 {
+            tree.insert_owned(StaticNode::new(k, value.clone()));
+        }
+        for k in 0..50 {
+            tree.delete(&(k * 2));
+        }
+        assert_eq!(51, Rc::strong_count(&value));
+    }
+    assert_eq!(1, Rc::strong_count(&value));
+}
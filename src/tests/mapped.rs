@@ -0,0 +1,102 @@
+use std::fmt::{Display, Formatter, Result};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use rand::seq::SliceRandom;
+
+use crate::{Mapped, MappedNode, Node, RBTree};
+
+type Entry = MappedNode<u64, [u8; 4]>;
+type MappedTree = RBTree<Entry, Mapped<Entry>>;
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_fmt(format_args!("{}:{}", if self.is_black() { 'B' } else { 'R' }, self.key()))
+    }
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("red-black-{}-{}", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_mapped_reopen() {
+    let path = temp_path("reopen");
+    let mut rng = rand::thread_rng();
+    let mut keys: Vec<u64> = (0..5000).collect();
+    keys.shuffle(&mut rng);
+
+    let mut tree: MappedTree = unsafe { RBTree::open(&path) }.unwrap();
+    for k in keys.iter() {
+        tree.insert_owned(Entry::new(*k, (*k as u32).to_le_bytes()));
+    }
+    for k in keys[..1000].iter() {
+        assert!(tree.delete(k));
+    }
+    tree.flush().unwrap();
+    let len = fs::metadata(&path).unwrap().len();
+    drop(tree);
+
+    let mut tree: MappedTree = unsafe { RBTree::open(&path) }.unwrap();
+    tree.validate();
+    assert_eq!(4000, tree.size());
+    for k in keys[..1000].iter() {
+        assert!(tree.search(k).is_none());
+    }
+    for k in keys[1000..].iter() {
+        assert_eq!((*k as u32).to_le_bytes(), *tree.search(k).unwrap().value());
+    }
+
+    // the slots of deleted nodes are reused before the file grows
+    for k in keys[..1000].iter() {
+        tree.insert_owned(Entry::new(*k, [0; 4]));
+    }
+    drop(tree);
+    assert_eq!(len, fs::metadata(&path).unwrap().len());
+    let tree: MappedTree = unsafe { RBTree::open(&path) }.unwrap();
+    tree.validate();
+    assert_eq!(5000, tree.size());
+    drop(tree);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_mapped_invalid_file() {
+    let path = temp_path("invalid");
+    fs::write(&path, [0xff; 4096]).unwrap();
+    let err = unsafe { MappedTree::open(&path) }.err().unwrap();
+    assert_eq!(ErrorKind::InvalidData, err.kind());
+
+    fs::remove_file(&path).unwrap();
+    drop(unsafe { MappedTree::open(&path) }.unwrap());
+    let err = unsafe { RBTree::<MappedNode<u64, u64>, Mapped<_>>::open(&path) }.err().unwrap();
+    assert_eq!(ErrorKind::InvalidData, err.kind());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_mapped_unflushed_changes() {
+    let path = temp_path("unflushed");
+    let mut tree: MappedTree = unsafe { RBTree::open(&path) }.unwrap();
+    for k in 0..100 {
+        tree.insert_owned(Entry::new(k, [0; 4]));
+    }
+    tree.flush().unwrap();
+    // a crash right after a flush leaves a valid tree
+    std::mem::forget(tree);
+    let mut tree: MappedTree = unsafe { RBTree::open(&path) }.unwrap();
+    assert_eq!(100, tree.size());
+
+    // and one after a change does not
+    assert!(tree.delete(&50));
+    std::mem::forget(tree);
+    let err = unsafe { MappedTree::open(&path) }.err().unwrap();
+    assert_eq!(ErrorKind::InvalidData, err.kind());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+
+use rand::Rng;
+
+use crate::{AggregateNode, Node, RBTree, RunningMedian, Sum};
+
+#[test]
+fn test_running_median() {
+    let mut rng = rand::thread_rng();
+    let mut median: RunningMedian<u32> = RunningMedian::new();
+    assert_eq!(None, median.median());
+    let mut window = VecDeque::new();
+    let size = if cfg!(miri) { 20 } else { 200 };
+    for _ in 0..size * 10 {
+        // few distinct keys, so most are repeated within the window
+        let key = rng.gen_range(0, size / 4);
+        median.insert(key);
+        window.push_back(key);
+        if window.len() > size as usize {
+            assert!(median.remove(&window.pop_front().unwrap()));
+        }
+        let mut sorted: Vec<u32> = window.iter().copied().collect();
+        sorted.sort_unstable();
+        assert_eq!(sorted.len(), median.len());
+        assert_eq!(Some(&sorted[(sorted.len() - 1) / 2]), median.median());
+        assert_eq!(Some(&sorted[sorted.len() - 1]), median.quantile(1.0));
+    }
+    assert!(!median.remove(&size));
+    while let Some(key) = window.pop_front() {
+        assert!(median.remove(&key));
+    }
+    assert!(median.is_empty());
+    assert_eq!(None, median.quantile(0.99));
+}
+
+#[test]
+fn test_weighted_range_count() {
+    // each key counts as many times as its value
+    let mut tree: RBTree<AggregateNode<i32, usize, Sum<usize>>> = RBTree::new();
+    for k in 0..100 {
+        tree.insert_owned(AggregateNode::new(k, k as usize % 3));
+    }
+    let weight = |keys: std::ops::Range<i32>| keys.map(|k| k as usize % 3).sum::<usize>();
+    assert_eq!(weight(0..100), tree.range_count(..));
+    assert_eq!(weight(10..50), tree.range_count(10..50));
+    assert_eq!(weight(10..51), tree.range_count(10..=50));
+    assert_eq!(Some(&1), tree.select(0).map(|node| node.key()));
+    assert_eq!(Some(&2), tree.select(1).map(|node| node.key()));
+    assert_eq!(Some(&2), tree.select(2).map(|node| node.key()));
+    assert!(tree.select(weight(0..100)).is_none());
+}
@@ -1,29 +1,67 @@
 use std::cmp::Ordering;
-use std::fmt::{Display, Formatter, Result, Write};
+use std::fmt::{Display, Formatter, Result};
 
+use std::ops::Bound;
+
+use rand::Rng;
 use rand::seq::SliceRandom;
 
-use crate::{Node, NodePtr, RBTree};
+use crate::{Node, NodeAlloc, NodePtr, PtrExt, RBTree};
 use crate::kv::KeyValue;
 
 mod validate;
 mod insert;
 mod delete;
+mod cursor;
+mod hint;
+mod handle;
+mod range;
+mod range_map;
+mod retain;
+mod sequence;
+mod multimap;
+mod nearest;
+mod prefix;
+mod alloc;
+mod aggregate;
+mod median;
+mod owned;
+mod rekey;
+mod mapped;
+mod region;
+mod inline;
+mod snapshot;
+#[cfg(feature = "serde")]
+mod serde;
+mod panics;
+mod threads;
 
 type KV32 = KeyValue<i32, i32>;
 type Color = crate::kv::Color;
 
+fn bound(rng: &mut impl Rng, max: i32) -> Bound<i32> {
+    match rng.gen_range(0, 3) {
+        0 => Bound::Included(rng.gen_range(-1, max + 1)),
+        1 => Bound::Excluded(rng.gen_range(-1, max + 1)),
+        _ => Bound::Unbounded
+    }
+}
+
+// A random range whose start is not after its end. Both bounds may exclude
+// the same key, which makes the range empty.
+fn range(rng: &mut impl Rng, max: i32) -> (Bound<i32>, Bound<i32>) {
+    loop {
+        let range = (bound(rng, max), bound(rng, max));
+        match range {
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) if s > e => continue,
+            _ => return range
+        }
+    }
+}
+
 impl Display for KV32 {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        f.write_char('(')?;
-        if !self.left().is_nil() {
-            f.write_fmt(format_args!("{},", self.left().node()))?
-        }
-        f.write_fmt(format_args!("{}:{}", if self.is_black() { 'B' } else { 'R' }, self.key()))?;
-        if !self.right().is_nil() {
-            f.write_fmt(format_args!(",{}", self.right().node()))?
-        }
-        f.write_char(')')
+        f.write_fmt(format_args!("{}:{}", if self.is_black() { 'B' } else { 'R' }, self.key()))
     }
 }
 
@@ -40,47 +78,49 @@ impl KV32 {
         }
         self
     }
-
-    fn insert_left(&mut self, node: &KV32) -> &mut KV32 {
-        if !self.left().is_nil() {
-            panic!("{} already has a left child {}", self.key(), self.left().node().key())
-        }
-        *self.left_mut() = Node::new(node);
-        self.left_mut().node_mut()
-    }
-
-    fn insert_right(&mut self, node: &KV32) -> &mut KV32 {
-        if !self.right().is_nil() {
-            panic!("{} already has a right child {}", self.key(), self.right().node().key())
-        }
-        *self.right_mut() = Node::new(node);
-        self.right_mut().node_mut()
-    }
 }
 
 impl RBTree<KV32> {
     fn search_for_update(&mut self, at: i32) -> &mut KV32 {
-        let mut current_ptr = &self.root;
+        let mut current_ptr = self.root;
         loop {
             if current_ptr.is_nil() {
                 panic!("Node {} does not exists", at)
             }
-            let current_node = current_ptr.node_mut();
+            let current_node = current_ptr.node_mut(&self.alloc);
             match current_node.key().cmp(&at) {
                 Ordering::Equal => { return current_node }
-                Ordering::Less => { current_ptr = current_node.right_mut() }
-                Ordering::Greater => { current_ptr = current_node.left_mut() }
+                Ordering::Less => { current_ptr = current_node.right() }
+                Ordering::Greater => { current_ptr = current_node.left() }
             }
         }
     }
 
+    fn set_color(&mut self, at: i32, color: Color) {
+        self.search_for_update(at).color(color);
+    }
+
     fn insert_left(&mut self, at: i32, key: i32, color: Color) {
-        self.search_for_update(at).insert_left(&KV32::same(key)).color(color);
+        let mut node = KV32::same(key);
+        node.color(color);
+        let ptr = self.alloc.alloc(node);
+        let parent = self.search_for_update(at);
+        if !parent.left().is_nil() {
+            panic!("{} already has a left child", at)
+        }
+        parent.set_left(ptr);
         self.size += 1
     }
 
     fn insert_right(&mut self, at: i32, key: i32, color: Color) {
-        self.search_for_update(at).insert_right(&KV32::same(key)).color(color);
+        let mut node = KV32::same(key);
+        node.color(color);
+        let ptr = self.alloc.alloc(node);
+        let parent = self.search_for_update(at);
+        if !parent.right().is_nil() {
+            panic!("{} already has a right child", at)
+        }
+        parent.set_right(ptr);
         self.size += 1
     }
 
@@ -97,11 +137,12 @@ impl RBTree<KV32> {
 fn test_random_operation() {
     let mut rng = rand::thread_rng();
     let mut index: RBTree<KV32> = RBTree::new();
-    let max_key = 1023;
+    // Miri runs orders of magnitude slower, so it gets a smaller workload.
+    let (max_key, rounds) = if cfg!(miri) { (63, 2) } else { (1023, 10) };
 
     let mut keys: Vec<i32> = (0..max_key).collect();
 
-    for _ in 0..10 {
+    for _ in 0..rounds {
         keys.shuffle(&mut rng);
         for k in keys.iter() {
             assert!(index.insert(&KV32::same(*k)));
@@ -115,7 +156,7 @@ fn test_random_operation() {
 
         keys.shuffle(&mut rng);
         for k in keys.iter() {
-            assert!(index.delete(&k));
+            assert!(index.delete(k));
             index.validate();
         }
     }
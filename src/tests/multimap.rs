@@ -0,0 +1,50 @@
+use crate::RBMultiMap;
+
+#[test]
+fn test_multimap_insertion_order() {
+    let mut map: RBMultiMap<i32, &str> = RBMultiMap::new();
+    map.insert(2, "b1");
+    map.insert(1, "a1");
+    map.insert(2, "b2");
+    map.insert(3, "c1");
+    map.insert(2, "b3");
+    assert_eq!(5, map.size());
+    assert_eq!(vec!["b1", "b2", "b3"], map.get_all(&2).cloned().collect::<Vec<_>>());
+    assert_eq!(vec!["a1"], map.get_all(&1).cloned().collect::<Vec<_>>());
+    assert_eq!(3, map.count(&2));
+    assert_eq!(1, map.count(&3));
+    assert_eq!(0, map.count(&4));
+    assert_eq!(0, map.count(&0));
+}
+
+#[test]
+fn test_multimap_remove() {
+    let mut map: RBMultiMap<i32, i32> = RBMultiMap::new();
+    for i in 0..300 {
+        map.insert(i % 3, i);
+    }
+    assert_eq!(Some(1), map.remove_one(&1));
+    assert_eq!(Some(&4), map.get_all(&1).next());
+    assert_eq!(99, map.count(&1));
+    assert_eq!(None, map.remove_one(&3));
+
+    assert_eq!(100, map.remove_all(&0));
+    assert_eq!(0, map.remove_all(&0));
+    assert_eq!(0, map.count(&0));
+    assert_eq!(199, map.size());
+    assert_eq!((0..100).map(|i| i * 3 + 2).collect::<Vec<_>>(), map.get_all(&2).cloned().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_multimap_owned_values() {
+    // values are moved in, so they need not be `Clone`
+    struct Owned(i32);
+    let mut map = RBMultiMap::new();
+    for i in 0..10 {
+        map.insert(i % 2, Owned(i));
+    }
+    assert_eq!(vec![1, 3, 5, 7, 9], map.get_all(&1).map(|v| v.0).collect::<Vec<_>>());
+    assert_eq!(1, map.remove_one(&1).unwrap().0);
+    assert_eq!(5, map.remove_all(&0));
+    assert_eq!(4, map.size());
+}
@@ -0,0 +1,46 @@
+use rand::Rng;
+
+use crate::{Node, RBTree};
+
+use super::KV32;
+
+#[test]
+fn test_nearest() {
+    let mut rng = rand::thread_rng();
+    let mut tree: RBTree<KV32> = RBTree::new();
+    let mut keys = Vec::new();
+    let count = if cfg!(miri) { 50 } else { 500 };
+    for _ in 0..count {
+        let key = rng.gen_range(-count * 10, count * 10);
+        if tree.insert(&KV32::same(key)) {
+            keys.push(key);
+        }
+    }
+    for _ in 0..100 {
+        let probe = rng.gen_range(-count * 11, count * 11);
+        let k = rng.gen_range(0, 20);
+        // ties go to the lesser key
+        keys.sort_by_key(|key| (key.abs_diff(probe), *key));
+        let nearest: Vec<i32> = tree.nearest(&probe, k).map(|node| *node.key()).collect();
+        assert_eq!(&keys[..k], &nearest[..], "{} {}", probe, k);
+    }
+    // asking for more than there are yields every key once
+    assert_eq!(keys.len(), tree.nearest(&0, keys.len() + 10).count());
+}
+
+#[test]
+fn test_nearest_edges() {
+    let mut tree: RBTree<KV32> = RBTree::new();
+    assert!(tree.nearest(&5, 3).next().is_none());
+    for k in [10, 20, 30] {
+        tree.insert(&KV32::same(k));
+    }
+    let nearest = |probe: i32, k: usize| -> Vec<i32> {
+        tree.nearest(&probe, k).map(|node| *node.key()).collect()
+    };
+    assert_eq!(vec![20, 10, 30], nearest(20, 3));
+    assert_eq!(vec![10, 20], nearest(15, 2));
+    assert_eq!(vec![10, 20, 30], nearest(i32::MIN, 5));
+    assert_eq!(vec![30, 20, 10], nearest(i32::MAX, 5));
+    assert!(nearest(20, 0).is_empty());
+}
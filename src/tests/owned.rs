@@ -0,0 +1,52 @@
+use std::rc::Rc;
+
+use crate::{KeyValue, Node, RBTree};
+
+type KVRc = KeyValue<i32, Rc<String>>;
+
+#[test]
+fn test_insert_owned() {
+    let value = Rc::new(String::from("value"));
+    let mut tree: RBTree<KVRc> = RBTree::new();
+    assert!(tree.insert_owned(KVRc::new(1, value.clone())).is_none());
+    assert!(tree.insert_owned(KVRc::new(2, value.clone())).is_none());
+    assert_eq!(3, Rc::strong_count(&value));
+
+    let other = Rc::new(String::from("other"));
+    let replaced = tree.insert_owned(KVRc::new(1, other.clone())).unwrap();
+    assert_eq!(1, *replaced.key());
+    assert!(Rc::ptr_eq(&value, replaced.value()));
+    assert!(Rc::ptr_eq(&other, tree.search(&1).unwrap().value()));
+    assert_eq!(2, tree.size());
+
+    drop(replaced);
+    assert_eq!(2, Rc::strong_count(&value));
+    drop(tree);
+    assert_eq!(1, Rc::strong_count(&value));
+    assert_eq!(1, Rc::strong_count(&other));
+}
+
+#[test]
+fn test_remove() {
+    let value = Rc::new(String::from("value"));
+    let mut tree: RBTree<KVRc> = RBTree::new();
+    for k in 0..100 {
+        tree.insert_owned(KVRc::new(k, value.clone()));
+    }
+    assert_eq!(101, Rc::strong_count(&value));
+
+    let removed = tree.remove(&50).unwrap();
+    assert_eq!(50, *removed.key());
+    assert!(tree.remove(&50).is_none());
+    assert_eq!(101, Rc::strong_count(&value));
+    drop(removed);
+    assert_eq!(100, Rc::strong_count(&value));
+
+    assert!(tree.delete(&10));
+    assert_eq!(99, Rc::strong_count(&value));
+    tree.retain(|node| node.key() % 2 == 0);
+    assert_eq!(49, Rc::strong_count(&value));
+    assert_eq!(48, tree.size());
+    assert_eq!(24, tree.extract_if(|node| *node.key() >= 50).count());
+    assert_eq!(25, Rc::strong_count(&value));
+}
@@ -0,0 +1,337 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::process::Command;
+
+use crate::{AggregateNode, Codec, KeyValue, Monoid, Node, RBTree};
+
+thread_local! {
+    static LIVE: Cell<usize> = const { Cell::new(0) };
+    // The number of comparisons, clones or decodes left before one panics.
+    static COUNTDOWN: Cell<usize> = const { Cell::new(usize::MAX) };
+    static PANIC_ON_DROP: Cell<Option<i32>> = const { Cell::new(None) };
+}
+
+fn tick() {
+    COUNTDOWN.with(|c| {
+        if c.get() == 0 {
+            c.set(usize::MAX);
+            panic!("injected panic");
+        }
+        c.set(c.get() - 1);
+    })
+}
+
+fn arm(countdown: usize) {
+    COUNTDOWN.with(|c| c.set(countdown));
+}
+
+fn disarm() {
+    COUNTDOWN.with(|c| c.set(usize::MAX));
+}
+
+fn live() -> usize {
+    LIVE.with(|l| l.get())
+}
+
+// A key or value whose callbacks panic on demand, and which counts its live
+// instances to detect leaks.
+#[derive(Debug)]
+struct Tracked(i32);
+
+impl Tracked {
+    fn new(n: i32) -> Tracked {
+        LIVE.with(|l| l.set(l.get() + 1));
+        Tracked(n)
+    }
+}
+
+impl Clone for Tracked {
+    fn clone(&self) -> Self {
+        tick();
+        Tracked::new(self.0)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        LIVE.with(|l| l.set(l.get() - 1));
+        if PANIC_ON_DROP.with(|p| p.get()) == Some(self.0) && !std::thread::panicking() {
+            panic!("injected panic on drop");
+        }
+    }
+}
+
+impl PartialEq for Tracked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Tracked {}
+
+impl PartialOrd for Tracked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Tracked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        tick();
+        self.0.cmp(&other.0)
+    }
+}
+
+impl Codec for Tracked {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.0.encode(w)
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        tick();
+        Ok(Tracked::new(i32::decode(r)?))
+    }
+}
+
+type Entry = KeyValue<Tracked, Tracked>;
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("{}:{}", if self.is_black() { 'B' } else { 'R' }, self.key().0))
+    }
+}
+
+fn entry(n: i32) -> Entry {
+    Entry::new(Tracked::new(n), Tracked::new(n))
+}
+
+fn filled(keys: impl Iterator<Item = i32>) -> RBTree<Entry> {
+    let mut tree = RBTree::new();
+    for k in keys {
+        tree.insert_owned(entry(k));
+    }
+    tree
+}
+
+// Checks that the tree is valid and holds exactly the given keys.
+fn check(tree: &RBTree<Entry>, keys: impl Iterator<Item = i32>) {
+    disarm();
+    tree.validate();
+    let mut count = 0;
+    for k in keys {
+        assert_eq!(k, tree.search(&Tracked::new(k)).unwrap().value().0);
+        count += 1;
+    }
+    assert_eq!(count, tree.size());
+}
+
+#[test]
+fn test_panic_in_cmp() {
+    let mut tree = filled((0..100).map(|k| k * 2));
+    // panics at every comparison in turn, until the operation gets through
+    for countdown in 0.. {
+        arm(countdown);
+        match catch_unwind(AssertUnwindSafe(|| tree.insert_owned(entry(51)))) {
+            Ok(replaced) => {
+                assert!(replaced.is_none());
+                break;
+            }
+            Err(_) => check(&tree, (0..100).map(|k| k * 2))
+        }
+    }
+    for countdown in 0.. {
+        arm(countdown);
+        match catch_unwind(AssertUnwindSafe(|| tree.delete(&Tracked::new(100)))) {
+            Ok(deleted) => {
+                assert!(deleted);
+                break;
+            }
+            Err(_) => check(&tree, (0..100).map(|k| k * 2).chain(Some(51)))
+        }
+    }
+    check(&tree, (0..100).map(|k| k * 2).filter(|k| *k != 100).chain(Some(51)));
+    drop(tree);
+    assert_eq!(0, live());
+}
+
+#[test]
+fn test_panic_in_clone() {
+    let mut tree = filled(0..10);
+    let node = entry(20);
+    for countdown in 0..2 {
+        arm(countdown);
+        assert!(catch_unwind(AssertUnwindSafe(|| tree.insert(&node))).is_err());
+        check(&tree, 0..10);
+        assert_eq!(22, live());
+    }
+    drop((tree, node));
+    assert_eq!(0, live());
+}
+
+#[test]
+fn test_panic_in_predicate() {
+    let mut tree = filled(0..100);
+    arm(0);
+    let retained = catch_unwind(AssertUnwindSafe(|| tree.retain(|node| {
+        tick();
+        node.key().0 % 2 == 0
+    })));
+    assert!(retained.is_err());
+    check(&tree, 0..100);
+    drop(tree);
+    assert_eq!(0, live());
+}
+
+#[test]
+fn test_panic_in_drop() {
+    PANIC_ON_DROP.with(|p| p.set(Some(40)));
+    let tree = filled(0..100);
+    assert!(catch_unwind(AssertUnwindSafe(|| drop(tree))).is_err());
+    assert_eq!(0, live());
+
+    let mut tree = filled(0..100);
+    let retained = catch_unwind(AssertUnwindSafe(|| tree.retain(|node| node.key().0 % 2 == 1)));
+    assert!(retained.is_err());
+    check(&tree, (0..50).map(|k| k * 2 + 1));
+    assert_eq!(100, live());
+
+    PANIC_ON_DROP.with(|p| p.set(Some(21)));
+    let extracted = catch_unwind(AssertUnwindSafe(|| tree.extract_if(|node| node.key().0 < 40).count()));
+    assert!(extracted.is_err());
+    check(&tree, (20..50).map(|k| k * 2 + 1));
+    assert_eq!(60, live());
+    PANIC_ON_DROP.with(|p| p.set(None));
+    drop(tree);
+    assert_eq!(0, live());
+}
+
+#[test]
+fn test_panic_in_decode() {
+    let mut bytes = Vec::new();
+    filled(0..50).write_snapshot(&mut bytes).unwrap();
+    assert_eq!(0, live());
+    for countdown in [0, 1, 37, 99] {
+        arm(countdown);
+        let read = catch_unwind(AssertUnwindSafe(|| RBTree::<Entry>::read_snapshot(&mut &bytes[..])));
+        assert!(read.is_err());
+        disarm();
+        assert_eq!(0, live());
+    }
+    let tree = RBTree::<Entry>::read_snapshot(&mut &bytes[..]).unwrap();
+    check(&tree, 0..50);
+}
+
+#[test]
+fn test_panic_in_delete_range() {
+    let mut tree = filled(0..100);
+    let (start, end) = (Tracked::new(20), Tracked::new(60));
+    for countdown in 0.. {
+        arm(countdown);
+        match catch_unwind(AssertUnwindSafe(|| tree.delete_range(&start..&end))) {
+            Ok(deleted) => {
+                assert_eq!(40, deleted);
+                break;
+            }
+            Err(_) => check(&tree, 0..100)
+        }
+    }
+    check(&tree, (0..20).chain(60..100));
+
+    PANIC_ON_DROP.with(|p| p.set(Some(70)));
+    assert!(catch_unwind(AssertUnwindSafe(|| tree.delete_range(&end..))).is_err());
+    PANIC_ON_DROP.with(|p| p.set(None));
+    check(&tree, 0..20);
+    drop((tree, start, end));
+    assert_eq!(0, live());
+}
+
+#[test]
+fn test_panic_in_rekey() {
+    let mut tree = filled(0..100);
+    let (old, key) = (Tracked::new(20), Tracked::new(150));
+    for countdown in 0.. {
+        arm(countdown);
+        match catch_unwind(AssertUnwindSafe(|| tree.rekey(&old, Tracked::new(150)))) {
+            Ok(rekeyed) => {
+                assert_eq!(20, rekeyed.unwrap().0);
+                break;
+            }
+            // every comparison runs before the node leaves the tree
+            Err(_) => check(&tree, 0..100)
+        }
+    }
+    // the value stays with the node under its new key
+    disarm();
+    tree.validate();
+    assert_eq!(100, tree.size());
+    assert_eq!(20, tree.search(&key).unwrap().value().0);
+    assert!(tree.search(&old).is_none());
+
+    // a key which is taken leaves the tree as it is, panic or not
+    for countdown in 0.. {
+        arm(countdown);
+        match catch_unwind(AssertUnwindSafe(|| tree.rekey(&key, Tracked::new(30)))) {
+            Ok(rekeyed) => {
+                assert_eq!(30, rekeyed.unwrap_err().0);
+                break;
+            }
+            Err(_) => assert_eq!(20, tree.search(&key).unwrap().value().0)
+        }
+    }
+    disarm();
+    tree.validate();
+    assert_eq!(100, tree.size());
+    assert_eq!(20, tree.search(&key).unwrap().value().0);
+    drop((tree, old, key));
+    assert_eq!(0, live());
+}
+
+// A sum whose `combine` panics on demand.
+#[derive(Clone)]
+struct Ticking(i32);
+
+impl Monoid for Ticking {
+    fn identity() -> Self {
+        Ticking(0)
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        tick();
+        Ticking(self.0 + other.0)
+    }
+}
+
+impl From<&i32> for Ticking {
+    fn from(value: &i32) -> Self {
+        Ticking(*value)
+    }
+}
+
+// A panic in `combine` aborts rather than unwind out of a half rebalanced
+// tree, so the test runs itself again in a child process to watch it abort.
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_panic_in_combine() {
+    if std::env::var_os("RED_BLACK_PANIC_IN_COMBINE").is_some() {
+        let mut tree: RBTree<AggregateNode<i32, i32, Ticking>> = RBTree::new();
+        for k in 0..20 {
+            tree.insert_owned(AggregateNode::new(k, k));
+        }
+        arm(10);
+        let _ = catch_unwind(AssertUnwindSafe(|| tree.insert_owned(AggregateNode::new(20, 20))));
+        unreachable!("the panic in combine was unwound");
+    }
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "tests::panics::test_panic_in_combine", "--nocapture", "--test-threads=1"])
+        .env("RED_BLACK_PANIC_IN_COMBINE", "1")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    // the test harness exits with 101 for a test which panicked
+    assert_ne!(Some(101), output.status.code(), "{}", stderr);
+    assert!(stderr.contains("Node::refresh panicked"), "{}", stderr);
+}
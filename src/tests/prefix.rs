@@ -0,0 +1,55 @@
+use rand::Rng;
+
+use crate::{KeyValue, Node, RBTree};
+
+#[test]
+fn test_string_prefix() {
+    let mut rng = rand::thread_rng();
+    let mut tree: RBTree<KeyValue<String, ()>> = RBTree::new();
+    let mut keys = Vec::new();
+    for _ in 0..if cfg!(miri) { 100 } else { 1000 } {
+        let len = rng.gen_range(0, 5);
+        let key: String = (0..len).map(|_| rng.gen_range(b'a', b'd') as char).collect();
+        if tree.insert(&KeyValue::new(key.clone(), ())) {
+            keys.push(key);
+        }
+    }
+    keys.sort();
+    for prefix in ["", "a", "b", "ab", "ca", "bcc", "abcd", "d", "\u{0}"] {
+        let expected: Vec<&String> = keys.iter().filter(|key| key.starts_with(prefix)).collect();
+        let found: Vec<&String> = tree.prefix_iter(prefix).map(|node| node.key()).collect();
+        assert_eq!(expected, found, "{:?}", prefix);
+    }
+}
+
+#[test]
+fn test_byte_prefix() {
+    let mut tree: RBTree<KeyValue<Vec<u8>, ()>> = RBTree::new();
+    for key in [&b"ab"[..], b"a", b"abc", b"b", b"", b"aa", b"ac", b"abz"] {
+        tree.insert(&KeyValue::new(key.to_vec(), ()));
+    }
+    let found: Vec<&[u8]> = tree.prefix_iter(&b"ab"[..]).map(|node| &node.key()[..]).collect();
+    assert_eq!(vec![&b"ab"[..], b"abc", b"abz"], found);
+    assert_eq!(8, tree.prefix_iter(&b""[..]).count());
+    assert_eq!(0, tree.prefix_iter(&b"abd"[..]).count());
+}
+
+#[test]
+fn test_tuple_prefix() {
+    let mut tenants: RBTree<KeyValue<(u32, u64), ()>> = RBTree::new();
+    let mut triples: RBTree<KeyValue<(u32, u32, u64), ()>> = RBTree::new();
+    for tenant in 0..10 {
+        for id in 0..tenant * 3 {
+            tenants.insert(&KeyValue::new((tenant, id as u64), ()));
+            triples.insert(&KeyValue::new((tenant, id % 3, id as u64), ()));
+        }
+    }
+    for tenant in 0..10 {
+        let ids: Vec<u64> = tenants.prefix_iter(&tenant).map(|node| node.key().1).collect();
+        assert_eq!((0..tenant as u64 * 3).collect::<Vec<_>>(), ids);
+        assert_eq!(tenant as usize * 3, triples.prefix_iter(&tenant).count());
+    }
+    assert_eq!(0, tenants.prefix_iter(&10).count());
+    let ids: Vec<u64> = triples.prefix_iter(&(4, 1)).map(|node| node.key().2).collect();
+    assert_eq!(vec![1, 4, 7, 10], ids);
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeBounds};
+
+use rand::Rng;
+
+use crate::{AggregateNode, Count, RBTree, Sum};
+
+use super::{range, KV32};
+
+// The keys in a range, which may be one that `BTreeMap::range` rejects.
+fn keys_in<V>(map: &BTreeMap<i32, V>, range: (Bound<i32>, Bound<i32>)) -> Vec<i32> {
+    map.keys().filter(|k| range.contains(*k)).copied().collect()
+}
+
+#[test]
+fn test_delete_range() {
+    let mut rng = rand::thread_rng();
+    let count = if cfg!(miri) { 100 } else { 3000 };
+    for _ in 0..if cfg!(miri) { 3 } else { 30 } {
+        let mut tree: RBTree<KV32> = RBTree::new();
+        let mut expected = BTreeMap::new();
+        for _ in 0..count {
+            let key = rng.gen_range(0, count);
+            tree.insert(&KV32::same(key));
+            expected.insert(key, key);
+        }
+        for _ in 0..5 {
+            let range = range(&mut rng, count);
+            let removed = keys_in(&expected, range);
+            removed.iter().for_each(|k| { expected.remove(k); });
+            assert_eq!(removed.len(), tree.delete_range(range));
+            tree.validate();
+            assert_eq!(expected.len(), tree.size());
+        }
+        for k in expected.keys() {
+            assert_eq!(k, tree.search(k).unwrap().value());
+        }
+    }
+}
+
+#[test]
+fn test_delete_range_edges() {
+    let mut tree: RBTree<KV32> = RBTree::new();
+    for k in 0..100 {
+        tree.insert(&KV32::same(k));
+    }
+    assert_eq!(0, tree.delete_range((Bound::Excluded(50), Bound::Included(50))));
+    for k in 0..100 {
+        assert_eq!(0, tree.delete_range((Bound::Excluded(k), Bound::Excluded(k))));
+    }
+    assert_eq!(100, tree.size());
+    assert_eq!(0, tree.delete_range(200..300));
+    assert_eq!(1, tree.delete_range(50..=50));
+    assert_eq!(10, tree.delete_range(..10));
+    assert_eq!(10, tree.delete_range(90..));
+    tree.validate();
+    assert_eq!(79, tree.size());
+    assert_eq!(79, tree.delete_range(..));
+    assert_eq!(0, tree.size());
+    tree.insert(&KV32::same(1));
+    tree.validate();
+    assert_eq!(1, *tree.search(&1).unwrap().value());
+}
+
+#[test]
+fn test_range_count() {
+    let mut rng = rand::thread_rng();
+    let mut tree: RBTree<AggregateNode<i32, (), Count>> = RBTree::new();
+    let mut expected = BTreeMap::new();
+    let count = if cfg!(miri) { 100 } else { 2000 };
+    for _ in 0..count {
+        let key = rng.gen_range(0, count);
+        tree.insert_owned(AggregateNode::new(key, ()));
+        expected.insert(key, ());
+    }
+    for _ in 0..count / 4 {
+        let key = rng.gen_range(0, count);
+        assert_eq!(expected.remove(&key).is_some(), tree.delete(&key));
+    }
+    for _ in 0..200 {
+        let range = range(&mut rng, count);
+        assert_eq!(keys_in(&expected, range).len(), tree.range_count(range), "{:?}", range);
+    }
+    assert_eq!(0, tree.range_count((Bound::Included(10), Bound::Excluded(5))));
+
+    // the counts stay up to date through the splits and joins
+    let range = range(&mut rng, count);
+    tree.delete_range(range);
+    let remaining = keys_in(&expected, range);
+    remaining.iter().for_each(|k| { expected.remove(k); });
+    for _ in 0..100 {
+        let range = self::range(&mut rng, count);
+        assert_eq!(keys_in(&expected, range).len(), tree.range_count(range), "{:?}", range);
+    }
+}
+
+#[test]
+fn test_delete_range_aggregate() {
+    let mut tree: RBTree<AggregateNode<i32, i64, Sum<i64>>> = RBTree::new();
+    for k in 0..1000 {
+        tree.insert_owned(AggregateNode::new(k, k as i64));
+    }
+    assert_eq!(400, tree.delete_range(300..700));
+    assert_eq!(Sum((0..300).chain(700..1000).sum()), tree.aggregate(..));
+    assert_eq!(Sum((200..300).chain(700..800).sum()), tree.aggregate(200..800));
+}
@@ -0,0 +1,92 @@
+use std::fmt::{Display, Formatter, Result};
+
+use rand::Rng;
+
+use crate::{Node, RangeEntry, RangeMap};
+
+impl Display for RangeEntry<u32, u8> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_fmt(format_args!("{}:{}", if self.is_black() { 'B' } else { 'R' }, self.key()))
+    }
+}
+
+// The maximal runs of equal values among the points, which a map should
+// hold exactly.
+fn runs(points: &[Option<u8>]) -> Vec<(u32, u32, u8)> {
+    let mut runs: Vec<(u32, u32, u8)> = Vec::new();
+    for (point, value) in points.iter().enumerate() {
+        let (point, value) = match value {
+            Some(value) => (point as u32, *value),
+            None => continue
+        };
+        match runs.last_mut() {
+            Some((_, end, last)) if *end == point && *last == value => *end += 1,
+            _ => runs.push((point, point + 1, value))
+        }
+    }
+    runs
+}
+
+fn check(map: &RangeMap<u32, u8>, points: &[Option<u8>]) {
+    map.tree.validate();
+    let ranges: Vec<(u32, u32, u8)> = map.iter().map(|(start, end, value)| (*start, *end, *value)).collect();
+    assert_eq!(runs(points), ranges);
+    assert_eq!(ranges.len(), map.len());
+    for (point, value) in points.iter().enumerate() {
+        assert_eq!(value.as_ref(), map.get(&(point as u32)), "{}", point);
+    }
+}
+
+#[test]
+fn test_range_map() {
+    let mut rng = rand::thread_rng();
+    let size = if cfg!(miri) { 50 } else { 500 };
+    for _ in 0..if cfg!(miri) { 2 } else { 20 } {
+        let mut map = RangeMap::new();
+        let mut points = vec![None; size as usize + 20];
+        for _ in 0..size {
+            let start = rng.gen_range(0, size);
+            let end = start + rng.gen_range(0, 20);
+            if rng.gen_ratio(1, 4) {
+                map.remove(start..end);
+                (start..end).for_each(|p| points[p as usize] = None);
+            } else {
+                // few values, so that neighbours often merge
+                let value = rng.gen_range(0, 3);
+                map.insert(start..end, value);
+                (start..end).for_each(|p| points[p as usize] = Some(value));
+            }
+        }
+        check(&map, &points);
+    }
+}
+
+#[test]
+fn test_range_map_split_and_merge() {
+    let mut map = RangeMap::new();
+    assert!(map.is_empty());
+    assert_eq!(None, map.get(&0));
+    map.insert(10..20, 'a');
+    map.insert(14..16, 'b');
+    assert_eq!(3, map.len());
+    assert_eq!(Some((&10, &14, &'a')), map.get_range(&13));
+    assert_eq!(Some((&14, &16, &'b')), map.get_range(&15));
+    assert_eq!(Some((&16, &20, &'a')), map.get_range(&16));
+    assert_eq!(None, map.get(&20));
+
+    // overwriting the middle with the outer value merges all three
+    map.insert(14..16, 'a');
+    assert_eq!(1, map.len());
+    assert_eq!(Some((&10, &20, &'a')), map.get_range(&15));
+
+    // adjacent ranges are merged on either side
+    map.insert(20..25, 'a');
+    map.insert(5..10, 'a');
+    assert_eq!(vec![(&5, &25, &'a')], map.iter().collect::<Vec<_>>());
+
+    map.remove(8..12);
+    map.insert(0..0, 'z');
+    map.remove(20..20);
+    assert_eq!(vec![(&5, &8, &'a'), (&12, &25, &'a')], map.iter().collect::<Vec<_>>());
+    assert_eq!(None, map.get(&10));
+}
@@ -0,0 +1,73 @@
+use std::fmt::{Display, Formatter, Result};
+use std::io::ErrorKind;
+
+use rand::seq::SliceRandom;
+
+use crate::{Node, RBTree, Region, RelNode};
+
+type Entry = RelNode<u32, u64>;
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_fmt(format_args!("{}:{}", if self.is_black() { 'B' } else { 'R' }, self.key()))
+    }
+}
+
+fn region(memory: &mut [u64]) -> Region<Entry> {
+    unsafe { Region::init(memory.as_mut_ptr() as *mut u8, memory.len() * 8) }
+}
+
+#[test]
+fn test_region_moved() {
+    let mut memory = vec![0u64; 4096];
+    let mut keys: Vec<u32> = (0..1000).collect();
+    keys.shuffle(&mut rand::thread_rng());
+    {
+        let mut tree = RBTree::from_region(region(&mut memory));
+        for k in keys.iter() {
+            tree.insert_owned(Entry::new(*k, *k as u64 * 3));
+        }
+        for k in keys[..300].iter() {
+            assert!(tree.delete(k));
+        }
+        tree.commit();
+    }
+
+    // another mapping of the same content at another address
+    let mut copy = memory.clone();
+    drop(memory);
+    let region: Region<Entry> = unsafe { Region::attach(copy.as_mut_ptr() as *mut u8, copy.len() * 8) }.unwrap();
+    let mut tree = RBTree::from_region(region);
+    tree.validate();
+    assert_eq!(700, tree.size());
+    for k in keys[..300].iter() {
+        assert!(tree.search(k).is_none());
+    }
+    for k in keys[300..].iter() {
+        assert_eq!(*k as u64 * 3, *tree.search(k).unwrap().value());
+    }
+    // released slots are reused
+    for k in keys[..300].iter() {
+        tree.insert_owned(Entry::new(*k, 0));
+    }
+    tree.validate();
+    assert_eq!(1000, tree.size());
+}
+
+#[test]
+#[should_panic(expected = "region is full")]
+fn test_region_full() {
+    let mut memory = vec![0u64; 64];
+    let mut tree = RBTree::from_region(region(&mut memory));
+    let capacity = tree.alloc().capacity() as u32;
+    for k in 0..=capacity {
+        tree.insert_owned(Entry::new(k, 0));
+    }
+}
+
+#[test]
+fn test_region_invalid() {
+    let mut memory = vec![0u64; 64];
+    let err = unsafe { Region::<Entry>::attach(memory.as_mut_ptr() as *mut u8, 512) }.err().unwrap();
+    assert_eq!(ErrorKind::InvalidData, err.kind());
+}
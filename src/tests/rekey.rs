@@ -0,0 +1,87 @@
+use std::rc::Rc;
+
+use rand::Rng;
+
+use crate::{AggregateNode, KeyValue, RBTree, Sum};
+
+type KVRc = KeyValue<i32, Rc<String>>;
+
+#[test]
+fn test_rekey() {
+    let value = Rc::new(String::from("value"));
+    let mut tree: RBTree<KVRc> = RBTree::new();
+    for k in 0..100 {
+        tree.insert_owned(KVRc::new(k * 2, value.clone()));
+    }
+    let node: *const KVRc = tree.search(&10).unwrap();
+    assert_eq!(Ok(10), tree.rekey(&10, 151));
+    // the same node and value, without a clone
+    assert!(std::ptr::eq(node, tree.search(&151).unwrap()));
+    assert!(tree.search(&10).is_none());
+    assert_eq!(101, Rc::strong_count(&value));
+    assert_eq!(100, tree.size());
+
+    assert_eq!(Err(7), tree.rekey(&11, 7));
+    assert_eq!(Err(20), tree.rekey(&12, 20));
+    assert_eq!(100, tree.size());
+}
+
+#[test]
+fn test_rekey_same_key() {
+    let mut tree: RBTree<KeyValue<i32, i32>> = RBTree::new();
+    for k in 0..100 {
+        tree.insert_owned(KeyValue::new(k * 2, k));
+    }
+    let node: *const KeyValue<i32, i32> = tree.search(&12).unwrap();
+    assert_eq!(Ok(12), tree.rekey(&12, 12));
+    assert!(std::ptr::eq(node, tree.search(&12).unwrap()));
+    assert_eq!(6, *tree.search(&12).unwrap().value());
+    // a key next to the old one keeps the node in place too
+    assert_eq!(Ok(12), tree.rekey(&12, 13));
+    assert!(std::ptr::eq(node, tree.search(&13).unwrap()));
+    assert_eq!(Ok(13), tree.rekey(&13, 11));
+    assert!(std::ptr::eq(node, tree.search(&11).unwrap()));
+    tree.validate();
+    assert_eq!(100, tree.size());
+}
+
+#[test]
+fn test_rekey_random() {
+    let mut rng = rand::thread_rng();
+    let mut tree: RBTree<KeyValue<i32, i32>> = RBTree::new();
+    let mut expected = std::collections::BTreeMap::new();
+    let count = if cfg!(miri) { 100 } else { 2000 };
+    for k in 0..count {
+        tree.insert_owned(KeyValue::new(k, k));
+        expected.insert(k, k);
+    }
+    for _ in 0..count {
+        let (old, key) = (rng.gen_range(0, count * 2), rng.gen_range(0, count * 2));
+        let rekeyed = tree.rekey(&old, key);
+        if expected.contains_key(&old) && (old == key || !expected.contains_key(&key)) {
+            assert_eq!(Ok(old), rekeyed);
+            let value = expected.remove(&old).unwrap();
+            expected.insert(key, value);
+        } else {
+            assert_eq!(Err(key), rekeyed);
+        }
+    }
+    tree.validate();
+    assert_eq!(expected.len(), tree.size());
+    for (k, v) in expected {
+        assert_eq!(v, *tree.search(&k).unwrap().value());
+    }
+}
+
+#[test]
+fn test_rekey_aggregate() {
+    let mut tree: RBTree<AggregateNode<i32, i64, Sum<i64>>> = RBTree::new();
+    for k in 0..100 {
+        tree.insert_owned(AggregateNode::new(k, k as i64));
+    }
+    assert_eq!(Ok(5), tree.rekey(&5, 1000));
+    assert_eq!(Sum((0..5).sum()), tree.aggregate(..5));
+    assert_eq!(Sum(5), tree.aggregate(100..));
+    assert_eq!(Sum((0..100).sum()), tree.aggregate(..));
+}
+
@@ -0,0 +1,69 @@
+use rand::seq::SliceRandom;
+
+use crate::{Node, RBTree};
+
+use super::KV32;
+
+fn shuffled_tree(max_key: i32) -> RBTree<KV32> {
+    let mut keys: Vec<i32> = (0..max_key).collect();
+    keys.shuffle(&mut rand::thread_rng());
+    let mut tree: RBTree<KV32> = RBTree::new();
+    for k in keys {
+        tree.insert(&KV32::same(k));
+    }
+    tree
+}
+
+// few removals are done with regular deletions
+#[test]
+fn test_retain_low_ratio() {
+    let mut tree = shuffled_tree(1023);
+    tree.retain(|node| node.key() % 100 != 0);
+    tree.validate();
+    assert_eq!(1012, tree.size());
+    for k in 0..1023 {
+        assert_eq!(k % 100 != 0, tree.search(&k).is_some());
+    }
+}
+
+// many removals are done by rebuilding the tree from the survivors
+#[test]
+fn test_retain_high_ratio() {
+    for max_key in 0..130 {
+        let mut tree = shuffled_tree(max_key);
+        tree.retain(|node| node.key() % 3 == 0);
+        tree.validate();
+        assert_eq!(((max_key + 2) / 3) as usize, tree.size());
+        for k in 0..max_key {
+            assert_eq!(k % 3 == 0, tree.search(&k).is_some());
+        }
+    }
+}
+
+#[test]
+fn test_retain_all_and_none() {
+    let mut tree = shuffled_tree(100);
+    tree.retain(|_| true);
+    assert_eq!(100, tree.size());
+    tree.retain(|_| false);
+    tree.validate();
+    assert_eq!("RBTree{size:0}", tree.to_string());
+}
+
+#[test]
+fn test_extract_if() {
+    let mut tree = shuffled_tree(1023);
+    let extracted: Vec<(i32, i32)> = tree.extract_if(|node| *node.key() >= 1000)
+        .map(|node| (*node.key(), *node.value()))
+        .collect();
+    assert_eq!((1000..1023).map(|k| (k, k)).collect::<Vec<_>>(), extracted);
+    tree.validate();
+    assert_eq!(1000, tree.size());
+
+    let mut extract = tree.extract_if(|node| node.key() % 2 == 1);
+    assert_eq!(1, *extract.next().unwrap().key());
+    drop(extract);
+    tree.validate();
+    assert_eq!(500, tree.size());
+    assert!(tree.search(&999).is_none());
+}
@@ -0,0 +1,103 @@
+use rand::Rng;
+
+use crate::{Node, NodeAlloc, NodePtr, PtrExt, SeqNode, Sequence};
+
+// Checks the red-black properties and the counts of a sequence, and returns
+// its content.
+fn check(sequence: &Sequence<u32>) -> Vec<u32> {
+    let tree = &sequence.tree;
+    assert!(tree.root.is_black(&tree.alloc), "The root node should be BLACK!");
+    let (_, count) = check_node(&tree.alloc, tree.root, false);
+    assert_eq!(tree.size(), count);
+    let values: Vec<u32> = sequence.iter().copied().collect();
+    assert_eq!(count, values.len());
+    for (index, value) in values.iter().enumerate() {
+        assert_eq!(Some(value), sequence.get(index));
+    }
+    assert_eq!(None, sequence.get(count));
+    values
+}
+
+// Returns the black height and the number of nodes of a subtree.
+fn check_node<A: NodeAlloc<SeqNode<u32>>>(alloc: &A, ptr: <SeqNode<u32> as Node>::Ptr, red_parent: bool) -> (usize, usize) {
+    if ptr.is_nil() {
+        return (1, 0);
+    }
+    let node = ptr.node(alloc);
+    assert!(!(red_parent && node.is_red()), "A node and its parent are both RED!");
+    let (left_height, left_count) = check_node(alloc, node.left(), node.is_red());
+    let (right_height, right_count) = check_node(alloc, node.right(), node.is_red());
+    assert_eq!(left_height, right_height, "variant black depth");
+    assert_eq!(left_count + right_count + 1, node.count, "stale count");
+    (left_height + node.is_black() as usize, node.count)
+}
+
+#[test]
+fn test_insert_remove_at() {
+    let mut rng = rand::thread_rng();
+    let mut sequence = Sequence::new();
+    let mut expected = Vec::new();
+    let count = if cfg!(miri) { 200 } else { 5000 };
+    for value in 0..count {
+        let index = rng.gen_range(0, expected.len() + 1);
+        sequence.insert_at(index, value);
+        expected.insert(index, value);
+    }
+    assert_eq!(expected, check(&sequence));
+    for _ in 0..count / 2 {
+        let index = rng.gen_range(0, expected.len());
+        assert_eq!(Some(expected.remove(index)), sequence.remove_at(index));
+        if rng.gen_ratio(1, 3) {
+            let index = rng.gen_range(0, expected.len() + 1);
+            sequence.insert_at(index, count);
+            expected.insert(index, count);
+        }
+    }
+    assert_eq!(expected, check(&sequence));
+    assert_eq!(None, sequence.remove_at(expected.len()));
+
+    *sequence.get_mut(3).unwrap() = 42;
+    expected[3] = 42;
+    while let Some(value) = sequence.remove_at(0) {
+        assert_eq!(expected.remove(0), value);
+    }
+    assert!(sequence.is_empty());
+}
+
+#[test]
+fn test_split_at() {
+    for len in 0..40 {
+        for index in 0..=len {
+            let (head, tail) = (0..len).collect::<Sequence<u32>>().split_at(index as usize);
+            assert_eq!((0..index).collect::<Vec<_>>(), check(&head));
+            assert_eq!((index..len).collect::<Vec<_>>(), check(&tail));
+        }
+    }
+
+    // splits trees shaped by random insertions and removals
+    let mut rng = rand::thread_rng();
+    let mut sequence = Sequence::new();
+    let count = if cfg!(miri) { 100 } else { 2000 };
+    for value in 0..count {
+        sequence.insert_at(rng.gen_range(0, value as usize + 1), value);
+    }
+    for _ in 0..count / 4 {
+        sequence.remove_at(rng.gen_range(0, sequence.len()));
+    }
+    let expected = check(&sequence);
+    let index = rng.gen_range(0, sequence.len());
+    let (mut head, tail) = sequence.split_at(index);
+    assert_eq!(expected[..index], check(&head)[..]);
+    assert_eq!(expected[index..], check(&tail)[..]);
+    head.insert_at(index / 2, count);
+    head.push(count);
+    check(&head);
+}
+
+#[test]
+#[should_panic(expected = "insertion index (is 2) should be <= len (is 1)")]
+fn test_insert_out_of_bounds() {
+    let mut sequence = Sequence::new();
+    sequence.push(0);
+    sequence.insert_at(2, 1);
+}
@@ -0,0 +1,48 @@
+use crate::{KeyValue, Node, RBMultiMap, RBTree};
+
+use super::KV32;
+
+#[test]
+fn test_serde_tree() {
+    let mut tree: RBTree<KV32> = RBTree::new();
+    for k in [3, 1, 2] {
+        tree.insert(&KV32::new(k, k * 10));
+    }
+    let json = serde_json::to_string(&tree).unwrap();
+    assert_eq!(r#"{"1":10,"2":20,"3":30}"#, json);
+
+    let copy: RBTree<KV32> = serde_json::from_str(&json).unwrap();
+    copy.validate();
+    assert_eq!(3, copy.size());
+    assert_eq!(20, *copy.search(&2).unwrap().value());
+
+    // unsorted input with duplicate keys keeps the last value
+    let copy: RBTree<KeyValue<String, u8>> = serde_json::from_str(r#"{"b":1,"a":2,"c":3,"a":4}"#).unwrap();
+    assert_eq!(3, copy.size());
+    assert_eq!(4, *copy.search(&"a".to_string()).unwrap().value());
+    let keys: Vec<String> = {
+        let mut cursor = copy.cursor_front();
+        let mut keys = vec![];
+        while let Some(node) = cursor.current() {
+            keys.push(node.key().clone());
+            cursor.move_next();
+        }
+        keys
+    };
+    assert_eq!(vec!["a", "b", "c"], keys);
+}
+
+#[test]
+fn test_serde_multimap() {
+    let mut map: RBMultiMap<i32, &str> = RBMultiMap::new();
+    map.insert(2, "x");
+    map.insert(1, "y");
+    map.insert(2, "z");
+    let json = serde_json::to_string(&map).unwrap();
+    assert_eq!(r#"[[1,"y"],[2,"x"],[2,"z"]]"#, json);
+
+    let mut copy: RBMultiMap<i32, String> = serde_json::from_str(r#"[[2,"x"],[1,"y"],[2,"z"]]"#).unwrap();
+    assert_eq!(vec!["x", "z"], copy.get_all(&2).collect::<Vec<_>>());
+    copy.insert(2, "w".to_string());
+    assert_eq!(vec!["x", "z", "w"], copy.get_all(&2).collect::<Vec<_>>());
+}
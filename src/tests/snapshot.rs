@@ -0,0 +1,71 @@
+use std::fmt::{Display, Formatter, Result};
+use std::io::ErrorKind;
+
+use rand::seq::SliceRandom;
+
+use crate::{KeyValue, Node, RBTree};
+
+use super::KV32;
+
+type Entry = KeyValue<(u32, String), Vec<u8>>;
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_fmt(format_args!("{}:{:?}", if self.is_black() { 'B' } else { 'R' }, self.key()))
+    }
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    let mut keys: Vec<u32> = (0..1000).collect();
+    keys.shuffle(&mut rand::thread_rng());
+    let mut tree: RBTree<Entry> = RBTree::new();
+    for k in keys {
+        tree.insert_owned(Entry::new((k % 7, k.to_string()), vec![k as u8; k as usize % 5]));
+    }
+
+    let mut bytes = Vec::new();
+    tree.write_snapshot(&mut bytes).unwrap();
+    let copy: RBTree<Entry> = RBTree::read_snapshot(&mut bytes.as_slice()).unwrap();
+    copy.validate();
+    assert_eq!(1000, copy.size());
+    let (mut a, mut b) = (tree.cursor_front(), copy.cursor_front());
+    while let Some(node) = a.current() {
+        let other = b.current().unwrap();
+        assert_eq!(node.key(), other.key());
+        assert_eq!(node.value(), other.value());
+        a.move_next();
+        b.move_next();
+    }
+    assert!(b.current().is_none());
+
+    let empty: RBTree<KV32> = RBTree::new();
+    let mut bytes = Vec::new();
+    empty.write_snapshot(&mut bytes).unwrap();
+    assert_eq!(0, RBTree::<KV32>::read_snapshot(&mut bytes.as_slice()).unwrap().size());
+}
+
+#[test]
+fn test_snapshot_corrupted() {
+    let mut tree: RBTree<KV32> = RBTree::new();
+    for k in 0..100 {
+        tree.insert(&KV32::same(k));
+    }
+    let mut bytes = Vec::new();
+    tree.write_snapshot(&mut bytes).unwrap();
+
+    let read = |bytes: &[u8]| RBTree::<KV32>::read_snapshot(&mut &bytes[..]).err().unwrap().kind();
+    let mut flipped = bytes.clone();
+    flipped[100] ^= 1;
+    assert_eq!(ErrorKind::InvalidData, read(&flipped));
+    let mut magic = bytes.clone();
+    magic[0] = b'X';
+    assert_eq!(ErrorKind::InvalidData, read(&magic));
+    assert_eq!(ErrorKind::UnexpectedEof, read(&bytes[..bytes.len() - 1]));
+
+    // swapping two entries breaks the order before the checksum is reached
+    let mut swapped = bytes.clone();
+    swapped[20..28].copy_from_slice(&bytes[28..36]);
+    swapped[28..36].copy_from_slice(&bytes[20..28]);
+    assert_eq!(ErrorKind::InvalidData, read(&swapped));
+}
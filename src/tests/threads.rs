@@ -0,0 +1,48 @@
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use crate::{Arena, Counting, Cursor, CursorMut, ExtractIf, GetAll, RBMultiMap, RBTree};
+
+use super::KV32;
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn test_send_sync() {
+    assert_send_sync::<KV32>();
+    assert_send_sync::<RBTree<KV32>>();
+    assert_send_sync::<RBTree<KV32, Arena<KV32>>>();
+    assert_send_sync::<RBTree<KV32, Counting<Arena<KV32>>>>();
+    assert_send_sync::<RBMultiMap<i32, String>>();
+    assert_send_sync::<Cursor<'_, KV32>>();
+    assert_send_sync::<CursorMut<'_, KV32>>();
+    assert_send_sync::<ExtractIf<'_, KV32, Arena<KV32>>>();
+    assert_send_sync::<GetAll<'_, i32, String>>();
+}
+
+#[test]
+fn test_threads() {
+    let mut tree: RBTree<KV32> = RBTree::new();
+    tree = thread::spawn(move || {
+        for k in 0..100 {
+            tree.insert(&KV32::same(k));
+        }
+        tree
+    }).join().unwrap();
+
+    let shared = Arc::new(RwLock::new(tree));
+    let readers: Vec<_> = (0..4).map(|i| {
+        let shared = shared.clone();
+        thread::spawn(move || {
+            let tree = shared.read().unwrap();
+            (1..100).filter(|k| k % 4 == i).all(|k| tree.search(&k).is_some())
+        })
+    }).collect();
+    shared.write().unwrap().delete(&0);
+    for reader in readers {
+        assert!(reader.join().unwrap());
+    }
+    let tree = shared.read().unwrap();
+    assert_eq!(99, tree.size());
+    tree.validate();
+}
@@ -1,33 +1,52 @@
 use std::fmt;
-use std::fmt::{Display, Formatter};
+use std::fmt::{Display, Formatter, Write};
 
-use crate::{Node, NodePtr, RBTree};
+use crate::{Node, NodeAlloc, NodePtr, PtrExt, RBTree};
 
 use super::KV32;
 
 pub(crate) type ValidationResult = Result<usize, String>;
 
-impl<N: Node + Display> Display for RBTree<N> {
+impl<N: Node + Display, A: NodeAlloc<N>> Display for RBTree<N, A> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         if self.root.is_nil() {
             f.write_fmt(format_args!("RBTree{{size:{}}}", self.size()))
         } else {
-            f.write_fmt(format_args!("RBTree{{size:{},tree:{}}}", self.size(), self.root.node()))
+            f.write_fmt(format_args!("RBTree{{size:{},tree:", self.size()))?;
+            self.fmt_node(self.root, f)?;
+            f.write_char('}')
         }
     }
 }
 
-impl<N: Node + Display> RBTree<N> {
+impl<N: Node + Display, A: NodeAlloc<N>> RBTree<N, A> {
+    fn fmt_node(&self, ptr: N::Ptr, f: &mut Formatter<'_>) -> fmt::Result {
+        let node = ptr.node(&self.alloc);
+        f.write_char('(')?;
+        if !node.left().is_nil() {
+            self.fmt_node(node.left(), f)?;
+            f.write_char(',')?;
+        }
+        f.write_fmt(format_args!("{}", node))?;
+        if !node.right().is_nil() {
+            f.write_char(',')?;
+            self.fmt_node(node.right(), f)?;
+        }
+        f.write_char(')')
+    }
+}
+
+impl<N: Node + Display, A: NodeAlloc<N>> RBTree<N, A> {
 
     pub(crate) fn validate(&self) -> usize {
-        if !self.root.is_nil() && !self.root.node().is_black() {
+        if !self.root.is_nil() && !self.root.node(&self.alloc).is_black() {
             panic!("The root node should be BLACK!");
         }
         match self.validate_node(&self.root, None) {
             Ok(black_depth) => { black_depth }
             Err(message) => {
-                println!("{}", self.to_string());
-                panic!(message)
+                println!("{}", self);
+                panic!("{}", message)
             }
         }
     }
@@ -36,24 +55,24 @@ impl<N: Node + Display> RBTree<N> {
         if node_ptr.is_nil() {
             return Ok(1);
         }
-        let node = node_ptr.node();
-        if parent_ptr.is_some() && parent_ptr.unwrap().is_red() && node.is_red() {
+        let node = node_ptr.node(&self.alloc);
+        if parent_ptr.is_some() && parent_ptr.unwrap().is_red(&self.alloc) && node.is_red() {
             return ValidationResult::Err(format!("A node ({:?}) and its parent are both RED!", node.key()));
         }
         if !node.left().is_nil() {
-            let left_key = node.left().node().key();
+            let left_key = node.left().node(&self.alloc).key();
             if node.key().le(left_key) {
-                return ValidationResult::Err(format!("A node ({:?}) is less than or equal to its left child ({:?})!", &node.key(), left_key));
+                return ValidationResult::Err(format!("A node ({:?}) is less than or equal to its left child ({:?})!", node.key(), left_key));
             }
         }
         if !node.right().is_nil() {
-            let right_key = node.right().node().key();
+            let right_key = node.right().node(&self.alloc).key();
             if node.key().ge(right_key) {
-                return ValidationResult::Err(format!("A node ({:?}) is greater than or equal to its right child ({:?})!", &node.key(), right_key));
+                return ValidationResult::Err(format!("A node ({:?}) is greater than or equal to its right child ({:?})!", node.key(), right_key));
             }
         }
-        let black_depth = self.validate_node(node.left(), Some(node_ptr))?;
-        if self.validate_node(node.right(), Some(node_ptr))? != black_depth {
+        let black_depth = self.validate_node(&node.left(), Some(node_ptr))?;
+        if self.validate_node(&node.right(), Some(node_ptr))? != black_depth {
             return ValidationResult::Err(format!("A node ({:?}) has variant black depth!", node.key()));
         }
         Ok(if node.is_black() { black_depth + 1 } else { black_depth })
@@ -62,7 +81,7 @@ impl<N: Node + Display> RBTree<N> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{NodePtr, RBTree};
+    use crate::RBTree;
     use crate::kv::Color::{BLACK, RED};
 
     use super::KV32;
@@ -72,7 +91,7 @@ mod tests {
     fn test_validate_1() {
         let mut tree: RBTree<KV32> = RBTree::new();
         tree.insert(&KV32::same(64));
-        tree.root.node_mut().color(RED);
+        tree.set_color(64, RED);
         tree.validate();
     }
 
@@ -81,9 +100,9 @@ mod tests {
     fn test_validate_2() {
         let mut tree: RBTree<KV32> = RBTree::new();
         tree.insert(&KV32::same(64));
-        tree.root.node_mut().color(BLACK)
-            .insert_left(&KV32::same(32).color(RED))
-            .insert_left(&KV32::same(16).color(RED));
+        tree.set_color(64, BLACK);
+        tree.insert_left(64, 32, RED);
+        tree.insert_left(32, 16, RED);
         println!("{}", tree);
         tree.validate();
     }
@@ -93,8 +112,8 @@ mod tests {
     fn test_validate_3() {
         let mut tree: RBTree<KV32> = RBTree::new();
         tree.insert(&KV32::same(64));
-        tree.root.node_mut().color(BLACK)
-            .insert_left(&KV32::same(65).color(RED));
+        tree.set_color(64, BLACK);
+        tree.insert_left(64, 65, RED);
         println!("{}", tree);
         tree.validate();
     }
@@ -104,8 +123,8 @@ mod tests {
     fn test_validate_4() {
         let mut tree: RBTree<KV32> = RBTree::new();
         tree.insert(&KV32::same(64));
-        tree.root.node_mut().color(BLACK)
-            .insert_left(&KV32::same(66).color(RED));
+        tree.set_color(64, BLACK);
+        tree.insert_left(64, 66, RED);
         println!("{}", tree);
         tree.validate();
     }
@@ -115,8 +134,8 @@ mod tests {
     fn test_validate_5() {
         let mut tree: RBTree<KV32> = RBTree::new();
         tree.insert(&KV32::same(64));
-        tree.root.node_mut().color(BLACK)
-            .insert_right(&KV32::same(64).color(RED));
+        tree.set_color(64, BLACK);
+        tree.insert_right(64, 64, RED);
         println!("{}", tree);
         tree.validate();
     }
@@ -126,8 +145,8 @@ mod tests {
     fn test_validate_6() {
         let mut tree: RBTree<KV32> = RBTree::new();
         tree.insert(&KV32::same(64));
-        tree.root.node_mut().color(BLACK)
-            .insert_right(&KV32::same(63).color(RED));
+        tree.set_color(64, BLACK);
+        tree.insert_right(64, 63, RED);
         println!("{}", tree);
         tree.validate();
     }
@@ -137,11 +156,11 @@ mod tests {
     fn test_validate_7() {
         let mut tree: RBTree<KV32> = RBTree::new();
         tree.insert(&KV32::same(64));
-        let root_node = tree.root.node_mut().color(BLACK);
-        root_node.insert_left(&KV32::same(32)).color(RED);
-        root_node.insert_right(&KV32::same(96)).color(RED);
-        tree.search_for_update(32).insert_left(&KV32::same(16)).color(BLACK);
+        tree.set_color(64, BLACK);
+        tree.insert_left(64, 32, RED);
+        tree.insert_right(64, 96, RED);
+        tree.insert_left(32, 16, BLACK);
         println!("{}", tree);
         tree.validate();
     }
-}
\ No newline at end of file
+}